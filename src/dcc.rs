@@ -0,0 +1,779 @@
+use chrono::prelude::*;
+use serde::Deserializer;
+use std::fmt::Formatter;
+use std::convert::TryFrom;
+use crate::valuesets::{
+    ValueSetValue, de_value_set_country, de_value_set_disease, de_value_set_test_result, de_value_set_test_type,
+    de_value_set_vaccine, de_value_set_vaccine_manufacturer, de_value_set_vaccine_product,
+};
+
+pub fn strip_uvci(uvci: &str) -> &str {
+    let bare_uvci_with_checksum = uvci.strip_prefix("URN:UVCI:").unwrap_or(uvci);
+    bare_uvci_with_checksum.rsplit_once("#").map(|(a, _b)| a).unwrap_or(bare_uvci_with_checksum)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EUDigitalGreenCertName {
+    #[serde(rename = "fn", default)]
+    pub surname: Option<String>,
+    #[serde(rename = "fnt", default)]
+    pub std_surname: Option<String>,
+    #[serde(rename = "gn", default)]
+    pub forename: Option<String>,
+    #[serde(rename = "gnt", default)]
+    pub std_forname: Option<String>,
+}
+
+impl EUDigitalGreenCertName {
+    pub fn native_name(&self) -> Option<String> {
+        let parts: Vec<&str> = [&self.forename, &self.surname].iter()
+            .filter_map(|p| p.as_deref())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
+    }
+
+    pub fn icao_name(&self) -> Option<String> {
+        match (self.std_surname.as_deref(), self.std_forname.as_deref()) {
+            (Some(s), Some(f)) if !f.is_empty() => Some(format!("{}<<{}", s, f)),
+            (Some(s), _) if !s.is_empty() => Some(s.to_string()),
+            (None, Some(f)) if !f.is_empty() => Some(format!("<<{}", f)),
+            _ => None
+        }
+    }
+
+    pub fn display_name(&self) -> String {
+        match self.native_name() {
+            Some(n) => n,
+            None => {
+                let parts: Vec<String> = [&self.std_forname, &self.std_surname].iter()
+                    .filter_map(|p| p.as_deref())
+                    .map(|p| p.replace('<', " ").trim().to_string())
+                    .filter(|p| !p.is_empty())
+                    .collect();
+                parts.join(" ")
+            }
+        }
+    }
+}
+
+/// An `eu_dgc_v1` schema version, `major.minor.patch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SchemaVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl SchemaVersion {
+    /// The first version to allow a date of birth with only the year, or year and month
+    const PARTIAL_DOB: SchemaVersion = SchemaVersion { major: 1, minor: 2, patch: 1 };
+
+    pub fn parse(ver: &str) -> Option<Self> {
+        let mut parts = ver.split('.').map(|p| p.parse::<u32>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Some(SchemaVersion { major, minor, patch }),
+            _ => None
+        }
+    }
+
+    /// 1.0.x through 1.3.x are supported.
+    pub fn is_supported(&self) -> bool {
+        self.major == 1 && self.minor <= 3
+    }
+}
+
+/// Why a well formed `hcert` is not a certificate this crate can decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsupportedCertificateType {
+    /// An `eu_dgc_v1` schema version other than 1.0.x to 1.3.x
+    SchemaVersion(String),
+    /// No known certificate group, only these fields
+    Fields(Vec<String>),
+}
+
+impl std::fmt::Display for UnsupportedCertificateType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsupportedCertificateType::SchemaVersion(v) => write!(f, "Unsupported certificate type with schema version {}", v),
+            UnsupportedCertificateType::Fields(fields) => write!(f, "Unsupported certificate type with fields {}", fields.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedCertificateType {}
+
+fn is_supported_schema_version(ver: &str) -> bool {
+    SchemaVersion::parse(ver).is_some_and(|v| v.is_supported())
+}
+
+/// Finds why a CWT payload that failed to deserialize as an `EHealthPayload` is an unsupported certificate type,
+/// rather than an invalid one.
+pub(crate) fn unsupported_certificate_type(payload: &[u8]) -> Option<UnsupportedCertificateType> {
+    let payload: serde_cbor::Value = serde_cbor::from_slice(payload).ok()?;
+    let hcert = cbor_map_get(&payload, &serde_cbor::Value::Integer(-260))?;
+    let eu_dgc_v1 = match cbor_map_get(hcert, &serde_cbor::Value::Integer(1)) {
+        Some(e) => e,
+        None => return unknown_fields(hcert, &[])
+    };
+
+    if let Some(serde_cbor::Value::Text(ver)) = cbor_map_get(eu_dgc_v1, &serde_cbor::Value::Text("ver".to_string())) {
+        if !is_supported_schema_version(ver) {
+            return Some(UnsupportedCertificateType::SchemaVersion(ver.clone()));
+        }
+    }
+    let has_group = ["v", "t", "r", "ex"].iter()
+        .any(|g| cbor_map_get(eu_dgc_v1, &serde_cbor::Value::Text(g.to_string())).is_some());
+    if has_group {
+        None
+    } else {
+        unknown_fields(eu_dgc_v1, &["ver", "nam", "dob"])
+    }
+}
+
+fn cbor_map_get<'a>(map: &'a serde_cbor::Value, key: &serde_cbor::Value) -> Option<&'a serde_cbor::Value> {
+    match map {
+        serde_cbor::Value::Map(m) => m.get(key),
+        _ => None
+    }
+}
+
+fn unknown_fields(map: &serde_cbor::Value, known: &[&str]) -> Option<UnsupportedCertificateType> {
+    let fields: Vec<String> = match map {
+        serde_cbor::Value::Map(m) => m.keys()
+            .filter(|k| !matches!(k, serde_cbor::Value::Text(t) if known.contains(&t.as_str())))
+            .map(cbor_key_name)
+            .collect(),
+        _ => return None
+    };
+    if fields.is_empty() {
+        None
+    } else {
+        Some(UnsupportedCertificateType::Fields(fields))
+    }
+}
+
+fn cbor_key_name(key: &serde_cbor::Value) -> String {
+    match key {
+        serde_cbor::Value::Integer(i) => i.to_string(),
+        serde_cbor::Value::Text(t) => t.clone(),
+        k => format!("{:?}", k)
+    }
+}
+
+#[derive(Debug)]
+pub struct EUDigitalGreenCertV1 {
+    pub ver: String,
+    pub name: EUDigitalGreenCertName,
+    pub dob: DateOfBirth,
+    pub group: EUDigitalGreenCertGroup,
+    pub unknown_fields: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum EUDigitalGreenCertGroup {
+    Vaccination(Vec<EUDigitalGreenCertVaccination>),
+    Test(Vec<EUDigitalGreenCertTest>),
+    Recovery(Vec<EUDigitalGreenCertRecovery>),
+    Exemption(Vec<EUDigitalGreenCertExemption>),
+}
+
+impl<'de> serde::Deserialize<'de> for EUDigitalGreenCertV1 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = EUDigitalGreenCertV1;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("struct EUDigitalGreenCertV1")
+            }
+
+            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
+                let mut ver = None;
+                let mut name = None;
+                let mut dob = None;
+                let mut group = None;
+                let mut unknown_fields = vec![];
+                while let Some(key) = map.next_key::<String>()? {
+                    let next_group = match key.as_str() {
+                        "ver" => {
+                            if ver.is_some() {
+                                return Err(serde::de::Error::duplicate_field("ver"));
+                            }
+                            ver = Some(map.next_value()?);
+                            None
+                        }
+                        "nam" => {
+                            if name.is_some() {
+                                return Err(serde::de::Error::duplicate_field("nam"));
+                            }
+                            name = Some(map.next_value()?);
+                            None
+                        }
+                        "dob" => {
+                            if dob.is_some() {
+                                return Err(serde::de::Error::duplicate_field("dob"));
+                            }
+                            dob = Some(map.next_value::<String>()?);
+                            None
+                        }
+                        "v" => Some(EUDigitalGreenCertGroup::Vaccination(map.next_value()?)),
+                        "t" => Some(EUDigitalGreenCertGroup::Test(map.next_value()?)),
+                        "r" => Some(EUDigitalGreenCertGroup::Recovery(map.next_value()?)),
+                        "ex" => Some(EUDigitalGreenCertGroup::Exemption(map.next_value()?)),
+                        _ => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                            unknown_fields.push(key);
+                            None
+                        }
+                    };
+                    if let Some(next_group) = next_group {
+                        if group.is_some() {
+                            return Err(serde::de::Error::custom("more than one of v, t, r or ex"));
+                        }
+                        group = Some(next_group);
+                    }
+                }
+
+                let ver: String = ver.ok_or_else(|| serde::de::Error::missing_field("ver"))?;
+                let version = match SchemaVersion::parse(&ver) {
+                    Some(v) if v.is_supported() => v,
+                    _ => return Err(serde::de::Error::custom(UnsupportedCertificateType::SchemaVersion(ver)))
+                };
+                let name = name.ok_or_else(|| serde::de::Error::missing_field("nam"))?;
+                let dob = dob.ok_or_else(|| serde::de::Error::missing_field("dob"))?;
+                let dob = DateOfBirth::parse(&dob, version).map_err(serde::de::Error::custom)?;
+                let group = match group {
+                    Some(g) => g,
+                    None if !unknown_fields.is_empty() => return Err(serde::de::Error::custom(
+                        UnsupportedCertificateType::Fields(unknown_fields)
+                    )),
+                    None => return Err(serde::de::Error::missing_field("v, t, r or ex"))
+                };
+
+                Ok(EUDigitalGreenCertV1 {
+                    ver,
+                    name,
+                    dob,
+                    group,
+                    unknown_fields,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+/// A date of birth, which from schema 1.2.1 may give only the year, or the year and month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOfBirth {
+    Year(i32),
+    YearMonth(i32, u32),
+    Date(NaiveDate),
+}
+
+impl DateOfBirth {
+    fn parse(s: &str, version: SchemaVersion) -> Result<Self, String> {
+        if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(DateOfBirth::Date(d));
+        }
+        if version >= SchemaVersion::PARTIAL_DOB {
+            let parts: Vec<&str> = s.split('-').collect();
+            let is_number = |p: &str, len: usize| p.len() == len && p.bytes().all(|b| b.is_ascii_digit());
+            match parts.as_slice() {
+                [y] if is_number(y, 4) => return Ok(DateOfBirth::Year(y.parse().unwrap())),
+                [y, m] if is_number(y, 4) && is_number(m, 2) => {
+                    let month = m.parse().unwrap();
+                    if (1..=12).contains(&month) {
+                        return Ok(DateOfBirth::YearMonth(y.parse().unwrap(), month));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(format!("invalid date of birth {} for schema version {}.{}.{}", s, version.major, version.minor, version.patch))
+    }
+
+    /// The full date, if the day is known.
+    pub fn date(&self) -> Option<NaiveDate> {
+        match self {
+            DateOfBirth::Date(d) => Some(*d),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for DateOfBirth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateOfBirth::Year(y) => write!(f, "{:04}", y),
+            DateOfBirth::YearMonth(y, m) => write!(f, "{:04}-{:02}", y, m),
+            DateOfBirth::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DateOrDateTime {
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl DateOrDateTime {
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            DateOrDateTime::Date(d) => *d,
+            DateOrDateTime::DateTime(d) => d.naive_local().date(),
+        }
+    }
+}
+
+impl std::fmt::Display for DateOrDateTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateOrDateTime::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            DateOrDateTime::DateTime(d) => write!(f, "{}", d.format("%Y-%m-%d %H:%M:%S %:z")),
+        }
+    }
+}
+
+fn parse_date_or_date_time(s: &str) -> Result<DateOrDateTime, String> {
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(DateOrDateTime::Date(d));
+    }
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%z"))
+        .map(DateOrDateTime::DateTime)
+        .map_err(|e| format!("invalid date {}: {}", s, e))
+}
+
+/// Every date other than the test sample and result times is a plain date in all schema versions.
+fn de_date<'de, D: serde::Deserializer<'de>>(d: D) -> Result<NaiveDate, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).and_then(|s|
+        NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+            .map_err(|e| serde::de::Error::custom(format!("invalid date {}: {}", s, e)))
+    )
+}
+
+fn de_date_or_date_time<'de, D: serde::Deserializer<'de>>(d: D) -> Result<DateOrDateTime, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).and_then(|s| parse_date_or_date_time(&s).map_err(serde::de::Error::custom))
+}
+
+fn de_opt_date_or_date_time<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<DateOrDateTime>, D::Error> {
+    de_date_or_date_time(d).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EUDigitalGreenCertVaccination {
+    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
+    pub targeted: ValueSetValue,
+    #[serde(rename = "vp", deserialize_with = "de_value_set_vaccine")]
+    pub vaccine: ValueSetValue,
+    #[serde(rename = "mp", deserialize_with = "de_value_set_vaccine_product")]
+    pub product: ValueSetValue,
+    #[serde(rename = "ma", deserialize_with = "de_value_set_vaccine_manufacturer")]
+    pub manufacturer: ValueSetValue,
+    #[serde(rename = "dn")]
+    pub dose: usize,
+    #[serde(rename = "sd")]
+    pub series: usize,
+    #[serde(rename = "dt", deserialize_with = "de_date")]
+    pub date: NaiveDate,
+    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
+    pub country: ValueSetValue,
+    #[serde(rename = "is")]
+    pub issuer: String,
+    #[serde(rename = "ci")]
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EUDigitalGreenCertTest {
+    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
+    pub targeted: ValueSetValue,
+    #[serde(rename = "tt", deserialize_with = "de_value_set_test_type")]
+    pub test_type: ValueSetValue,
+    #[serde(rename = "nm", default)]
+    pub name: Option<String>,
+    #[serde(rename = "ma", default)]
+    pub device: Option<String>,
+    #[serde(rename = "sc", deserialize_with = "de_date_or_date_time")]
+    pub sample_time: DateOrDateTime,
+    #[serde(rename = "dr", default, deserialize_with = "de_opt_date_or_date_time")]
+    pub result_time: Option<DateOrDateTime>,
+    #[serde(rename = "tr", deserialize_with = "de_value_set_test_result")]
+    pub result: ValueSetValue,
+    #[serde(rename = "tc", default)]
+    pub centre: Option<String>,
+    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
+    pub country: ValueSetValue,
+    #[serde(rename = "is")]
+    pub issuer: String,
+    #[serde(rename = "ci")]
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EUDigitalGreenCertRecovery {
+    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
+    pub targeted: ValueSetValue,
+    #[serde(rename = "fr", deserialize_with = "de_date")]
+    pub first_positive_test_date: NaiveDate,
+    #[serde(rename = "df", deserialize_with = "de_date")]
+    pub valid_from_date: NaiveDate,
+    #[serde(rename = "du", deserialize_with = "de_date")]
+    pub valid_until_date: NaiveDate,
+    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
+    pub country: ValueSetValue,
+    #[serde(rename = "is")]
+    pub issuer: String,
+    #[serde(rename = "ci")]
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EUDigitalGreenCertExemption {
+    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
+    pub targeted: ValueSetValue,
+    #[serde(rename = "es", default)]
+    pub status: Option<String>,
+    #[serde(rename = "df", deserialize_with = "de_date")]
+    pub valid_from_date: NaiveDate,
+    #[serde(rename = "du", deserialize_with = "de_date")]
+    pub valid_until_date: NaiveDate,
+    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
+    pub country: ValueSetValue,
+    #[serde(rename = "is")]
+    pub issuer: String,
+    #[serde(rename = "ci")]
+    pub id: String,
+}
+
+#[derive(Debug)]
+pub struct EHealthHcert {
+    pub eu_dgc_v1: EUDigitalGreenCertV1,
+    pub unknown_fields: Vec<String>,
+}
+
+impl<'de> serde::Deserialize<'de> for EHealthHcert {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = EHealthHcert;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("struct EHealthHcert")
+            }
+
+            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
+                let mut eu_dgc_v1 = None;
+                let mut unknown_fields = vec![];
+                while let Some(key) = map.next_key::<serde_cbor::Value>()? {
+                    match key {
+                        serde_cbor::Value::Integer(1) => {
+                            if eu_dgc_v1.is_some() {
+                                return Err(serde::de::Error::duplicate_field("eu_dgc_v1"));
+                            }
+                            eu_dgc_v1 = Some(map.next_value()?);
+                        }
+                        f => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                            unknown_fields.push(cbor_key_name(&f));
+                        }
+                    }
+                }
+
+                let eu_dgc_v1 = match eu_dgc_v1 {
+                    Some(e) => e,
+                    None if !unknown_fields.is_empty() => return Err(serde::de::Error::custom(
+                        UnsupportedCertificateType::Fields(unknown_fields)
+                    )),
+                    None => return Err(serde::de::Error::missing_field("eu_dgc_v1"))
+                };
+
+                Ok(EHealthHcert {
+                    eu_dgc_v1,
+                    unknown_fields,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("", &[], Visitor)
+    }
+}
+
+#[derive(Debug)]
+pub struct EHealthPayload {
+    pub iss: String,
+    pub sub: Option<String>,
+    pub aud: Vec<String>,
+    pub iat: DateTime<Utc>,
+    pub nbf: Option<DateTime<Utc>>,
+    pub exp: DateTime<Utc>,
+    pub cti: Option<Vec<u8>>,
+    pub hcert: EHealthHcert,
+}
+
+fn cwt_numeric_date<E: serde::de::Error>(value: serde_cbor::Value, claim: &str) -> Result<DateTime<Utc>, E> {
+    let seconds = match value {
+        serde_cbor::Value::Integer(t) => i64::try_from(t).ok(),
+        serde_cbor::Value::Float(t) if t.is_finite() && t >= i64::MIN as f64 && t < i64::MAX as f64 => Some(t.trunc() as i64),
+        serde_cbor::Value::Float(_) => None,
+        _ => return Err(E::custom(format!("{} must be a numeric date", claim)))
+    };
+    seconds.and_then(|s| chrono::Utc.timestamp_opt(s, 0).single())
+        .ok_or_else(|| E::custom(format!("{} is out of range", claim)))
+}
+
+impl<'de> serde::Deserialize<'de> for EHealthPayload {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = EHealthPayload;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("struct EHealthPayload")
+            }
+
+            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
+                let mut iss = None;
+                let mut sub = None;
+                let mut aud = None;
+                let mut iat = None;
+                let mut nbf = None;
+                let mut exp = None;
+                let mut cti = None;
+                let mut hcert = None;
+                while let Some(key) = map.next_key::<serde_cbor::Value>()? {
+                    let key = match key {
+                        serde_cbor::Value::Integer(k) => k,
+                        _ => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                            continue;
+                        }
+                    };
+                    match key {
+                        1 => {
+                            if iss.is_some() {
+                                return Err(serde::de::Error::duplicate_field("iss"));
+                            }
+                            iss = Some(map.next_value()?);
+                        }
+                        2 => {
+                            if sub.is_some() {
+                                return Err(serde::de::Error::duplicate_field("sub"));
+                            }
+                            sub = Some(map.next_value()?);
+                        }
+                        3 => {
+                            if aud.is_some() {
+                                return Err(serde::de::Error::duplicate_field("aud"));
+                            }
+                            aud = Some(match map.next_value()? {
+                                serde_cbor::Value::Text(a) => vec![a],
+                                serde_cbor::Value::Array(a) => a.into_iter().filter_map(|a| match a {
+                                    serde_cbor::Value::Text(a) => Some(a),
+                                    _ => None
+                                }).collect(),
+                                _ => return Err(serde::de::Error::custom("aud must be a string or array"))
+                            });
+                        }
+                        4 => {
+                            if exp.is_some() {
+                                return Err(serde::de::Error::duplicate_field("exp"));
+                            }
+                            exp = Some(cwt_numeric_date(map.next_value()?, "exp")?);
+                        }
+                        5 => {
+                            if nbf.is_some() {
+                                return Err(serde::de::Error::duplicate_field("nbf"));
+                            }
+                            nbf = Some(cwt_numeric_date(map.next_value()?, "nbf")?);
+                        }
+                        6 => {
+                            if iat.is_some() {
+                                return Err(serde::de::Error::duplicate_field("iat"));
+                            }
+                            iat = Some(cwt_numeric_date(map.next_value()?, "iat")?);
+                        }
+                        7 => {
+                            if cti.is_some() {
+                                return Err(serde::de::Error::duplicate_field("cti"));
+                            }
+                            cti = match map.next_value()? {
+                                serde_cbor::Value::Bytes(b) => Some(b),
+                                _ => return Err(serde::de::Error::custom("cti must be a byte string"))
+                            };
+                        }
+                        -260 => {
+                            if hcert.is_some() {
+                                return Err(serde::de::Error::duplicate_field("hcert"));
+                            }
+                            hcert = Some(map.next_value()?);
+                        }
+                        _ => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let iss = iss.ok_or_else(|| serde::de::Error::missing_field("iss"))?;
+                let iat = iat.ok_or_else(|| serde::de::Error::missing_field("iat"))?;
+                let exp = exp.ok_or_else(|| serde::de::Error::missing_field("exp"))?;
+                let hcert = hcert.ok_or_else(|| serde::de::Error::missing_field("hcert"))?;
+
+                Ok(EHealthPayload {
+                    iss,
+                    sub,
+                    aud: aud.unwrap_or_default(),
+                    iat,
+                    nbf,
+                    exp,
+                    cti,
+                    hcert,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("", &[], Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, map, text, with_field, without_field};
+
+    fn decode(eu_dgc_v1: serde_cbor::Value) -> Result<EUDigitalGreenCertV1, serde_cbor::Error> {
+        serde_cbor::from_slice(&serde_cbor::to_vec(&eu_dgc_v1).unwrap())
+    }
+
+    /// A 1.0.x test certificate, with sample and result times as datetimes.
+    fn test_v1_0() -> serde_cbor::Value {
+        let test = test_support::test("2021-05-01T10:00:00Z");
+        let entry = match &test {
+            serde_cbor::Value::Map(m) => match m.get(&text("t")) {
+                Some(serde_cbor::Value::Array(a)) => a[0].clone(),
+                _ => unreachable!()
+            },
+            _ => unreachable!()
+        };
+        let entry = with_field(entry, "dr", text("2021-05-01T12:30:00+02:00"));
+        with_field(with_field(test, "ver", text("1.0.0")), "t", serde_cbor::Value::Array(vec![entry]))
+    }
+
+    #[test]
+    fn parses_schema_versions() {
+        assert_eq!(SchemaVersion::parse("1.3.0"), Some(SchemaVersion { major: 1, minor: 3, patch: 0 }));
+        assert!(SchemaVersion::parse("1.0.4").unwrap().is_supported());
+        assert!(!SchemaVersion::parse("1.4.0").unwrap().is_supported());
+        assert!(!SchemaVersion::parse("2.0.0").unwrap().is_supported());
+        assert_eq!(SchemaVersion::parse("1.3"), None);
+        assert_eq!(SchemaVersion::parse("1.x.0"), None);
+    }
+
+    #[test]
+    fn decodes_1_0_test_with_datetimes() {
+        let cert = decode(test_v1_0()).unwrap();
+        assert_eq!(cert.ver, "1.0.0");
+        assert_eq!(cert.dob, DateOfBirth::Date(NaiveDate::from_ymd_opt(1964, 8, 12).unwrap()));
+        match &cert.group {
+            EUDigitalGreenCertGroup::Test(t) => {
+                assert!(matches!(t[0].sample_time, DateOrDateTime::DateTime(d) if d.timestamp() == 1619863200));
+                assert!(matches!(t[0].result_time, Some(DateOrDateTime::DateTime(d)) if d.timestamp() == 1619865000));
+            }
+            g => panic!("unexpected group {:?}", g)
+        }
+    }
+
+    #[test]
+    fn decodes_1_3_vaccination() {
+        let cert = decode(test_support::vaccination(1)).unwrap();
+        assert_eq!(cert.ver, "1.3.0");
+        match &cert.group {
+            EUDigitalGreenCertGroup::Vaccination(v) => assert_eq!(v[0].date, NaiveDate::from_ymd_opt(2021, 5, 29).unwrap()),
+            g => panic!("unexpected group {:?}", g)
+        }
+    }
+
+    #[test]
+    fn partial_dob_needs_schema_1_2_1() {
+        let cert = decode(with_field(test_support::vaccination(1), "dob", text("1964-08"))).unwrap();
+        assert_eq!(cert.dob, DateOfBirth::YearMonth(1964, 8));
+        assert_eq!(cert.dob.to_string(), "1964-08");
+        let cert = decode(with_field(test_support::vaccination(1), "dob", text("1964"))).unwrap();
+        assert_eq!(cert.dob, DateOfBirth::Year(1964));
+        assert_eq!(cert.dob.date(), None);
+
+        assert!(decode(with_field(test_support::vaccination(1), "dob", text("1964-13"))).is_err());
+        assert!(decode(with_field(test_v1_0(), "dob", text("1964-08"))).is_err());
+        assert!(decode(with_field(test_v1_0(), "dob", text("1964"))).is_err());
+    }
+
+    #[test]
+    fn only_test_times_accept_datetimes() {
+        assert!(decode(with_field(test_support::vaccination(1), "dob", text("1964-08-12T00:00:00Z"))).is_err());
+        assert!(decode(with_field(test_v1_0(), "dob", text("1964-08-12T00:00:00Z"))).is_err());
+
+        let vaccination = with_field(
+            without_field(test_support::vaccination(1), "v"), "v",
+            serde_cbor::Value::Array(vec![map(vec![
+                (text("tg"), text("840539006")),
+                (text("vp"), text("1119349007")),
+                (text("mp"), text("EU/1/20/1528")),
+                (text("ma"), text("ORG-100030215")),
+                (text("dn"), serde_cbor::Value::Integer(1)),
+                (text("sd"), serde_cbor::Value::Integer(2)),
+                (text("dt"), text("2021-05-29T10:00:00Z")),
+                (text("co"), text("DE")),
+                (text("is"), text("Robert Koch-Institut")),
+                (text("ci"), text("URN:UVCI:01DE/IZ12345A/5CWLU12RNOB9RXSEOP6FG8#S")),
+            ])]),
+        );
+        assert!(decode(vaccination).is_err());
+    }
+
+    #[test]
+    fn refuses_unsupported_schema_versions() {
+        for ver in ["1.4.0", "2.0.0", "1.3"] {
+            let err = decode(with_field(test_support::vaccination(1), "ver", text(ver))).unwrap_err();
+            assert!(err.to_string().contains(&UnsupportedCertificateType::SchemaVersion(ver.to_string()).to_string()));
+        }
+    }
+
+    #[test]
+    fn cwt_numeric_date_accepts_integers_and_floats() {
+        let from_int: DateTime<Utc> = cwt_numeric_date::<serde::de::value::Error>(
+            serde_cbor::Value::Integer(1620000000), "exp"
+        ).unwrap();
+        assert_eq!(from_int.timestamp(), 1620000000);
+        let from_float: DateTime<Utc> = cwt_numeric_date::<serde::de::value::Error>(
+            serde_cbor::Value::Float(1620000000.5), "exp"
+        ).unwrap();
+        assert_eq!(from_float.timestamp(), 1620000000);
+    }
+
+    #[test]
+    fn cwt_numeric_date_rejects_out_of_range_values() {
+        for value in [
+            serde_cbor::Value::Integer(i128::MAX),
+            serde_cbor::Value::Integer(i64::MAX as i128),
+            serde_cbor::Value::Float(f64::NAN),
+            serde_cbor::Value::Float(f64::INFINITY),
+            serde_cbor::Value::Float(1e300),
+            serde_cbor::Value::Text("2021-05-01".to_string()),
+        ] {
+            assert!(cwt_numeric_date::<serde::de::value::Error>(value, "exp").is_err());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use coset::TaggedCborSerializable;
+use std::io::Read;
+use crate::dcc::EHealthPayload;
+use crate::error::Error;
+
+#[derive(Debug)]
+pub struct HC1Certificate {
+    pub cose: coset::CoseSign1,
+    pub payload: EHealthPayload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+pub enum DecodeMode {
+    #[default]
+    #[serde(rename = "strict")]
+    Strict,
+    #[serde(rename = "tolerant")]
+    Tolerant,
+}
+
+pub fn decode_hc1(data: &str, mode: DecodeMode) -> Result<HC1Certificate, Error> {
+    let hc_data_b45 = data.strip_prefix("HC1:").ok_or(Error::Unsupported)?;
+    let hc_data_deflated = base45::decode(hc_data_b45)
+        .map_err(Error::Base45)?;
+
+    let mut deflater = flate2::read::ZlibDecoder::new(&hc_data_deflated[..]);
+    let mut hc_data = Vec::new();
+    deflater.read_to_end(&mut hc_data).map_err(Error::Inflate)?;
+
+    let cose = coset::CoseSign1::from_tagged_slice(&hc_data)
+        .map_err(Error::Cose)?;
+
+    let payload_bytes = cose.payload.as_ref().ok_or(Error::NoPayload)?;
+    let payload = serde_cbor::from_slice::<EHealthPayload>(payload_bytes).map_err(|e| {
+        match crate::dcc::unsupported_certificate_type(payload_bytes) {
+            Some(t) if e.is_data() => Error::UnsupportedCertificateType(t),
+            _ => Error::from_payload_error(e)
+        }
+    })?;
+
+    if mode == DecodeMode::Strict {
+        let unknown_fields: Vec<&str> = payload.hcert.unknown_fields.iter()
+            .chain(payload.hcert.eu_dgc_v1.unknown_fields.iter())
+            .map(|f| f.as_str())
+            .collect();
+        if !unknown_fields.is_empty() {
+            return Err(Error::Schema(serde::de::Error::custom(
+                format!("unknown fields {}", unknown_fields.join(", "))
+            )));
+        }
+    }
+
+    Ok(HC1Certificate {
+        cose,
+        payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dcc::UnsupportedCertificateType;
+    use crate::test_support::{self, map, text, with_field, without_field, KeyIdLocation};
+
+    fn decode(eu_dgc_v1: serde_cbor::Value, mode: DecodeMode) -> Result<HC1Certificate, Error> {
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", 1622316073, eu_dgc_v1), &test_support::signing_key(), b"kid", KeyIdLocation::Protected,
+        );
+        decode_hc1(&data, mode)
+    }
+
+    #[test]
+    fn decodes_vaccination() {
+        let cert = decode(test_support::vaccination(1), DecodeMode::Strict).unwrap();
+        assert_eq!(cert.payload.iss, "DE");
+        assert!(matches!(cert.payload.hcert.eu_dgc_v1.group, crate::dcc::EUDigitalGreenCertGroup::Vaccination(_)));
+    }
+
+    #[test]
+    fn encoding_errors_carry_their_cause() {
+        let err = decode_hc1("HC1:not base45!", DecodeMode::Strict).unwrap_err();
+        assert!(matches!(err, Error::Base45(_)));
+        assert!(std::error::Error::source(&err).is_some());
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, b"not COSE").unwrap();
+        let data = format!("HC1:{}", base45::encode_from_buffer(encoder.finish().unwrap()));
+        let err = decode_hc1(&data, DecodeMode::Strict).unwrap_err();
+        assert!(matches!(err, Error::Cose(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn classifies_unknown_schema_version() {
+        let eu_dgc_v1 = with_field(test_support::vaccination(1), "ver", text("2.0.0"));
+        match decode(eu_dgc_v1, DecodeMode::Strict) {
+            Err(Error::UnsupportedCertificateType(t)) =>
+                assert_eq!(t, UnsupportedCertificateType::SchemaVersion("2.0.0".to_string())),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn classifies_unknown_group() {
+        let eu_dgc_v1 = with_field(without_field(test_support::vaccination(1), "v"), "xx", serde_cbor::Value::Array(vec![]));
+        match decode(eu_dgc_v1, DecodeMode::Tolerant) {
+            Err(Error::UnsupportedCertificateType(t)) =>
+                assert_eq!(t, UnsupportedCertificateType::Fields(vec!["xx".to_string()])),
+            r => panic!("unexpected result {:?}", r)
+        }
+    }
+
+    #[test]
+    fn invalid_payloads_are_schema_errors() {
+        let eu_dgc_v1 = without_field(test_support::vaccination(1), "v");
+        assert!(matches!(decode(eu_dgc_v1, DecodeMode::Strict), Err(Error::Schema(_))));
+
+        let eu_dgc_v1 = with_field(test_support::vaccination(1), "dob", map(vec![]));
+        assert!(matches!(decode(eu_dgc_v1, DecodeMode::Strict), Err(Error::Schema(_))));
+    }
+
+    #[test]
+    fn strict_mode_refuses_unknown_fields() {
+        let eu_dgc_v1 = with_field(test_support::vaccination(1), "xx", text("national extension"));
+        assert!(matches!(decode(eu_dgc_v1, DecodeMode::Strict), Err(Error::Schema(_))));
+        let eu_dgc_v1 = with_field(test_support::vaccination(1), "xx", text("national extension"));
+        assert!(decode(eu_dgc_v1, DecodeMode::Tolerant).is_ok());
+    }
+}
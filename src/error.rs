@@ -0,0 +1,330 @@
+#[derive(Debug)]
+pub enum Error {
+    Unsupported,
+    Base45(base45::DecodeError),
+    Base32,
+    Inflate(std::io::Error),
+    Cose(serde_cbor::Error),
+    NoPayload,
+    Cbor(serde_cbor::Error),
+    Schema(serde_cbor::Error),
+    UnsupportedCertificateType(crate::dcc::UnsupportedCertificateType),
+    Nzcp(&'static str),
+    ShcEncoding(&'static str),
+    ShcSession(&'static str),
+    Jws(&'static str, Box<dyn std::error::Error + Send + Sync>),
+    Json(serde_json::Error),
+    Fhir(&'static str),
+    UnknownSigningKey,
+    Signature(SignatureError),
+    SigningCertificate(&'static str),
+    Revoked,
+    Expired,
+    NotYetValid,
+    RevocationCheck(openssl::error::ErrorStack),
+    InvalidPass(&'static str),
+    Signing(&'static str, Box<dyn std::error::Error + Send + Sync>),
+    PassStore(PassStoreError),
+}
+
+impl Error {
+    pub(crate) fn from_payload_error(e: serde_cbor::Error) -> Self {
+        if e.is_data() {
+            Error::Schema(e)
+        } else {
+            Error::Cbor(e)
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Unsupported => "unsupported",
+            Error::Base45(_) => "base45",
+            Error::Base32 => "base32",
+            Error::Inflate(_) => "inflate",
+            Error::Cose(_) | Error::NoPayload => "cose",
+            Error::Cbor(_) => "cbor",
+            Error::Schema(_) | Error::InvalidPass(_) => "schema",
+            Error::UnsupportedCertificateType(_) => "unsupported_type",
+            Error::Nzcp(_) => "nzcp",
+            Error::ShcEncoding(_) => "shc",
+            Error::ShcSession(_) => "shc_session",
+            Error::Jws(_, _) => "jws",
+            Error::Json(_) | Error::Fhir(_) => "schema",
+            Error::UnknownSigningKey | Error::Signature(_) | Error::SigningCertificate(_) => "signature",
+            Error::Revoked | Error::RevocationCheck(_) => "revocation",
+            Error::Expired | Error::NotYetValid => "validity",
+            Error::Signing(_, _) => "signing",
+            Error::PassStore(_) => "pass_store",
+        }
+    }
+
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Error::Unsupported | Error::Base45(_) | Error::Inflate(_) | Error::Cose(_) | Error::NoPayload |
+            Error::Cbor(_) | Error::Schema(_) | Error::InvalidPass(_) | Error::ShcEncoding(_) | Error::Jws(_, _) |
+            Error::Json(_) | Error::Fhir(_) | Error::Base32 | Error::Nzcp(_) => 400,
+            Error::UnsupportedCertificateType(_) | Error::UnknownSigningKey | Error::Signature(_) |
+            Error::SigningCertificate(_) | Error::Expired | Error::NotYetValid => 422,
+            Error::ShcSession(_) => 404,
+            Error::Revoked => 410,
+            Error::RevocationCheck(_) | Error::Signing(_, _) | Error::PassStore(_) => 500,
+        }
+    }
+
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            Error::Unsupported => "Not an eHealth QR code",
+            Error::Base45(_) => "Invalid Base45",
+            Error::Base32 => "Invalid Base32",
+            Error::Inflate(_) => "Invalid DEFLATE encoding",
+            Error::Cose(_) => "Invalid COSE data",
+            Error::NoPayload => "No payload in COSE data",
+            Error::Cbor(_) | Error::Schema(_) => "Invalid payload",
+            Error::UnsupportedCertificateType(_) => "Unsupported certificate type",
+            Error::Nzcp(m) => m,
+            Error::ShcEncoding(_) | Error::Jws(_, _) => "Invalid SMART Health Card",
+            Error::ShcSession(m) => m,
+            Error::Json(_) | Error::Fhir(_) => "Invalid payload",
+            Error::UnknownSigningKey => "Signed by an unknown key",
+            Error::Signature(_) => "Invalid signature",
+            Error::SigningCertificate(m) => m,
+            Error::Revoked => "This certificate has been revoked",
+            Error::Expired => "This certificate has expired",
+            Error::NotYetValid => "This certificate is not valid yet",
+            Error::RevocationCheck(_) => "Unable to check revocation status",
+            Error::InvalidPass(_) => "Invalid pass",
+            Error::Signing(_, _) => "Unable to generate pass",
+            Error::PassStore(_) => "Unable to register pass for updates",
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unsupported => f.write_str("Unsupported QR code"),
+            Error::Base45(e) => write!(f, "Can't decode Base45: {}", e),
+            Error::Base32 => f.write_str("Can't decode Base32"),
+            Error::Inflate(e) => write!(f, "Can't decode DEFLATE: {}", e),
+            Error::Cose(e) => write!(f, "Can't decode COSE: {}", e),
+            Error::NoPayload => f.write_str("No COSE payload"),
+            Error::Cbor(e) => write!(f, "Can't decode CBOR: {}", e),
+            Error::Schema(e) => write!(f, "Can't decode payload: {}", e),
+            Error::UnsupportedCertificateType(e) => write!(f, "Can't decode payload: {}", e),
+            Error::Nzcp(e) => write!(f, "Invalid NZ COVID Pass: {}", e),
+            Error::ShcEncoding(e) => write!(f, "Can't decode SMART Health Card: {}", e),
+            Error::ShcSession(e) => write!(f, "SMART Health Card scan session: {}", e),
+            Error::Jws(c, e) => write!(f, "Can't decode JWS {}: {}", c, e),
+            Error::Json(e) => write!(f, "Can't decode payload: {}", e),
+            Error::Fhir(e) => write!(f, "Invalid FHIR bundle: {}", e),
+            Error::UnknownSigningKey => f.write_str("No known signing key"),
+            Error::Signature(e) => write!(f, "Signature verification failed: {}", e),
+            Error::SigningCertificate(e) => write!(f, "Signing certificate rejected: {}", e),
+            Error::Revoked => f.write_str("Certificate revoked"),
+            Error::Expired => f.write_str("Certificate expired"),
+            Error::NotYetValid => f.write_str("Certificate not yet valid"),
+            Error::RevocationCheck(e) => write!(f, "Can't check revocation status: {}", e),
+            Error::InvalidPass(e) => write!(f, "Unable to create pkpass: {}", e),
+            Error::Signing(c, e) => write!(f, "{}: {}", c, e),
+            Error::PassStore(e) => write!(f, "Pass update store: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Base45(e) => Some(e),
+            Error::Inflate(e) => Some(e),
+            Error::Cose(e) => Some(e),
+            Error::Cbor(e) | Error::Schema(e) => Some(e),
+            Error::UnsupportedCertificateType(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Signature(e) => Some(e),
+            Error::RevocationCheck(e) => Some(e),
+            Error::Jws(_, e) | Error::Signing(_, e) => Some(e.as_ref()),
+            Error::PassStore(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Why a signature could not be verified with a key.
+#[derive(Debug)]
+pub enum SignatureError {
+    UnsupportedAlgorithm(String),
+    KeyType(&'static str),
+    Length,
+    Crypto(openssl::error::ErrorStack),
+    Mismatch,
+    /// Every candidate key failed, with the reason for each
+    NoKeyMatched(Vec<SignatureError>),
+}
+
+impl From<openssl::error::ErrorStack> for SignatureError {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        SignatureError::Crypto(e)
+    }
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::UnsupportedAlgorithm(a) => write!(f, "Unsupported signing alg: {}", a),
+            SignatureError::KeyType(e) => f.write_str(e),
+            SignatureError::Length => f.write_str("invalid signature length"),
+            SignatureError::Crypto(e) => write!(f, "{}", e),
+            SignatureError::Mismatch => f.write_str("signature failed to verify"),
+            SignatureError::NoKeyMatched(errors) => f.write_str(
+                &errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SignatureError::Crypto(e) => Some(e),
+            SignatureError::NoKeyMatched(errors) => errors.first().map(|e| e as &(dyn std::error::Error + 'static)),
+            _ => None,
+        }
+    }
+}
+
+/// Why a trust list source could not be loaded, with what was being done when it failed.
+#[derive(Debug)]
+pub enum TrustListError {
+    Io(String, std::io::Error),
+    Http(String, reqwest::Error),
+    Json(String, serde_json::Error),
+    Crypto(String, openssl::error::ErrorStack),
+}
+
+impl std::fmt::Display for TrustListError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustListError::Io(c, e) => write!(f, "{}: {}", c, e),
+            TrustListError::Http(c, e) => write!(f, "{}: {}", c, e),
+            TrustListError::Json(c, e) => write!(f, "{}: {}", c, e),
+            TrustListError::Crypto(c, e) => write!(f, "{}: {}", c, e),
+        }
+    }
+}
+
+impl std::error::Error for TrustListError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TrustListError::Io(_, e) => Some(e),
+            TrustListError::Http(_, e) => Some(e),
+            TrustListError::Json(_, e) => Some(e),
+            TrustListError::Crypto(_, e) => Some(e),
+        }
+    }
+}
+
+/// Why the pass update store could not be used.
+#[derive(Debug)]
+pub enum PassStoreError {
+    Database(rusqlite::Error),
+    Crypto(openssl::error::ErrorStack),
+    Json(serde_json::Error),
+    Apns(reqwest::Error),
+}
+
+impl From<rusqlite::Error> for PassStoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        PassStoreError::Database(e)
+    }
+}
+
+impl From<openssl::error::ErrorStack> for PassStoreError {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        PassStoreError::Crypto(e)
+    }
+}
+
+impl From<reqwest::Error> for PassStoreError {
+    fn from(e: reqwest::Error) -> Self {
+        PassStoreError::Apns(e)
+    }
+}
+
+impl std::fmt::Display for PassStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassStoreError::Database(e) => write!(f, "database: {}", e),
+            PassStoreError::Crypto(e) => write!(f, "crypto: {}", e),
+            PassStoreError::Json(e) => write!(f, "revocation hashes: {}", e),
+            PassStoreError::Apns(e) => write!(f, "APNs client: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PassStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PassStoreError::Database(e) => Some(e),
+            PassStoreError::Crypto(e) => Some(e),
+            PassStoreError::Json(e) => Some(e),
+            PassStoreError::Apns(e) => Some(e),
+        }
+    }
+}
+
+/// Why a value set directory could not be loaded, with the file that failed.
+#[derive(Debug)]
+pub enum ValueSetError {
+    Io(String, std::io::Error),
+    Json(String, serde_json::Error),
+    IdMismatch(String),
+}
+
+impl std::fmt::Display for ValueSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueSetError::Io(c, e) => write!(f, "{}: {}", c, e),
+            ValueSetError::Json(c, e) => write!(f, "{}: {}", c, e),
+            ValueSetError::IdMismatch(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for ValueSetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ValueSetError::Io(_, e) => Some(e),
+            ValueSetError::Json(_, e) => Some(e),
+            ValueSetError::IdMismatch(_) => None,
+        }
+    }
+}
+
+/// Why a Google Cloud service account key could not be used to sign Google Wallet JWTs.
+#[derive(Debug)]
+pub enum ServiceAccountKeyError {
+    Json(serde_json::Error),
+    PrivateKey(openssl::error::ErrorStack),
+    NotRsa,
+}
+
+impl std::fmt::Display for ServiceAccountKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceAccountKeyError::Json(e) => write!(f, "Invalid service account key: {}", e),
+            ServiceAccountKeyError::PrivateKey(e) => write!(f, "Invalid service account private key: {}", e),
+            ServiceAccountKeyError::NotRsa => f.write_str("Service account private key is not an RSA key"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceAccountKeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServiceAccountKeyError::Json(e) => Some(e),
+            ServiceAccountKeyError::PrivateKey(e) => Some(e),
+            ServiceAccountKeyError::NotRsa => None,
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use chrono::prelude::*;
+use crate::error::{Error, ServiceAccountKeyError};
+use crate::localisation::{PASS_LANGUAGES, translate_label};
+use crate::pass::{Pass, PassField, PassFieldValue, Rgb};
+
+pub const SAVE_URL: &str = "https://pay.google.com/gp/v/save/";
+
+#[derive(Debug, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+}
+
+pub struct GoogleWalletSigningKey {
+    pub issuer_id: String,
+    pub class_suffix: String,
+    pub service_account_email: String,
+    pub private_key: openssl::pkey::PKey<openssl::pkey::Private>,
+}
+
+impl GoogleWalletSigningKey {
+    /// Loads the RS256 key from a Google Cloud service account JSON key file
+    pub fn from_service_account_json(
+        issuer_id: &str, class_suffix: &str, json: &[u8],
+    ) -> Result<Self, ServiceAccountKeyError> {
+        let key: ServiceAccountKey = serde_json::from_slice(json).map_err(ServiceAccountKeyError::Json)?;
+        let private_key = openssl::pkey::PKey::private_key_from_pem(key.private_key.as_bytes())
+            .map_err(ServiceAccountKeyError::PrivateKey)?;
+        if private_key.rsa().is_err() {
+            return Err(ServiceAccountKeyError::NotRsa);
+        }
+        Ok(GoogleWalletSigningKey {
+            issuer_id: issuer_id.to_string(),
+            class_suffix: class_suffix.to_string(),
+            service_account_email: key.client_email,
+            private_key,
+        })
+    }
+
+    pub fn class_id(&self) -> String {
+        format!("{}.{}", self.issuer_id, self.class_suffix)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenericObject {
+    pub id: String,
+    #[serde(rename = "classId")]
+    pub class_id: String,
+    pub state: GenericObjectState,
+    #[serde(rename = "cardTitle")]
+    pub card_title: LocalizedString,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subheader: Option<LocalizedString>,
+    pub header: LocalizedString,
+    #[serde(rename = "textModulesData", skip_serializing_if = "Vec::is_empty")]
+    pub text_modules: Vec<TextModuleData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub barcode: Option<Barcode>,
+    #[serde(rename = "hexBackgroundColor", skip_serializing_if = "Option::is_none")]
+    pub hex_background_colour: Option<String>,
+    #[serde(rename = "validTimeInterval", skip_serializing_if = "Option::is_none")]
+    pub valid_time_interval: Option<TimeInterval>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum GenericObjectState {
+    #[serde(rename = "ACTIVE")]
+    Active,
+    #[serde(rename = "INACTIVE")]
+    Inactive,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocalizedString {
+    #[serde(rename = "defaultValue")]
+    pub default_value: TranslatedString,
+    #[serde(rename = "translatedValues", skip_serializing_if = "Vec::is_empty")]
+    pub translated_values: Vec<TranslatedString>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TranslatedString {
+    pub language: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextModuleData {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    pub body: String,
+    #[serde(rename = "localizedHeader", skip_serializing_if = "Option::is_none")]
+    pub localized_header: Option<LocalizedString>,
+    #[serde(rename = "localizedBody", skip_serializing_if = "Option::is_none")]
+    pub localized_body: Option<LocalizedString>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Barcode {
+    #[serde(rename = "type")]
+    pub barcode_type: BarcodeType,
+    pub value: String,
+    #[serde(rename = "alternateText", skip_serializing_if = "Option::is_none")]
+    pub alternate_text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum BarcodeType {
+    #[serde(rename = "QR_CODE")]
+    QR,
+    #[serde(rename = "PDF_417")]
+    PDF417,
+    #[serde(rename = "AZTEC")]
+    Aztec,
+    #[serde(rename = "CODE_128")]
+    Code128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeInterval {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<DateTimeValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTimeValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DateTimeValue {
+    pub date: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SaveToWalletClaims<'a> {
+    iss: &'a str,
+    aud: &'static str,
+    typ: &'static str,
+    iat: i64,
+    origins: Vec<String>,
+    payload: SaveToWalletPayload<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct SaveToWalletPayload<'a> {
+    #[serde(rename = "genericClasses")]
+    generic_classes: Vec<GenericClass>,
+    #[serde(rename = "genericObjects")]
+    generic_objects: Vec<&'a GenericObject>,
+}
+
+#[derive(Debug, Serialize)]
+struct GenericClass {
+    id: String,
+}
+
+/// Renders a pass for Google Wallet as a generic object, with the same fields as its Apple Wallet rendering.
+pub fn pass_to_generic_object(pass: &Pass, signing_key: &GoogleWalletSigningKey) -> Result<GenericObject, Error> {
+    let primary_field = pass.primary_fields.first()
+        .ok_or(Error::InvalidPass("pass has no primary field"))?;
+
+    let text_modules = pass.header_fields.iter()
+        .chain(pass.secondary_fields.iter())
+        .chain(pass.aux_fields.iter())
+        .chain(pass.back_fields.iter())
+        .map(|f| TextModuleData {
+            id: f.key.clone(),
+            header: Some(f.label.clone()),
+            body: field_value(f),
+            localized_header: Some(localized_label(&f.label)),
+            localized_body: localized_value(f),
+        })
+        .collect();
+
+    Ok(GenericObject {
+        id: format!("{}.{}", signing_key.issuer_id, object_id_suffix(&pass.serial)),
+        class_id: signing_key.class_id(),
+        state: GenericObjectState::Active,
+        card_title: localized_label(&pass.logo_text),
+        subheader: Some(localized_label(&primary_field.label)),
+        header: localized_value(primary_field).unwrap_or_else(|| untranslated(field_value(primary_field))),
+        text_modules,
+        barcode: Some(Barcode {
+            barcode_type: BarcodeType::QR,
+            value: pass.barcode.clone(),
+            alternate_text: None,
+        }),
+        hex_background_colour: Some(hex_colour(pass.colours.background)),
+        valid_time_interval: pass.exp_date.map(|exp| TimeInterval {
+            start: None,
+            end: Some(DateTimeValue {
+                date: exp.to_rfc3339(),
+            }),
+        }),
+    })
+}
+
+/// Signs a "Save to Google Wallet" JWT for the object and returns the URL to redirect the user to.
+pub fn sign_save_url(object: &GenericObject, signing_key: &GoogleWalletSigningKey) -> Result<String, Error> {
+    let header = serde_json::json!({
+        "alg": "RS256",
+        "typ": "JWT",
+    });
+    let claims = SaveToWalletClaims {
+        iss: &signing_key.service_account_email,
+        aud: "google",
+        typ: "savetowallet",
+        iat: Utc::now().timestamp(),
+        origins: vec![],
+        payload: SaveToWalletPayload {
+            generic_classes: vec![GenericClass {
+                id: signing_key.class_id(),
+            }],
+            generic_objects: vec![object],
+        },
+    };
+
+    let header_bytes = serde_json::to_vec(&header).map_err(|e| Error::Signing("Unable to serialize JWT header", e.into()))?;
+    let claims_bytes = serde_json::to_vec(&claims).map_err(|e| Error::Signing("Unable to serialize JWT claims", e.into()))?;
+    let signing_input = format!(
+        "{}.{}",
+        base64::encode_config(&header_bytes, base64::URL_SAFE_NO_PAD),
+        base64::encode_config(&claims_bytes, base64::URL_SAFE_NO_PAD)
+    );
+
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &signing_key.private_key)
+        .map_err(|e| Error::Signing("Unable to sign JWT", e.into()))?;
+    signer.update(signing_input.as_bytes())
+        .map_err(|e| Error::Signing("Unable to sign JWT", e.into()))?;
+    let signature = signer.sign_to_vec()
+        .map_err(|e| Error::Signing("Unable to sign JWT", e.into()))?;
+
+    Ok(format!(
+        "{}{}.{}", SAVE_URL, signing_input, base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+/// PassKit formats date fields on the device, Google Wallet shows the value as given.
+fn field_value(field: &PassField) -> String {
+    match &field.value {
+        PassFieldValue::Text(value) => value.clone(),
+        PassFieldValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+        PassFieldValue::DateOf(date_time) => date_time.format("%Y-%m-%d").to_string(),
+        PassFieldValue::DateTime(date_time) => date_time.format("%Y-%m-%d %H:%M %:z").to_string(),
+    }
+}
+
+fn untranslated(value: String) -> LocalizedString {
+    LocalizedString {
+        default_value: TranslatedString {
+            language: "en".to_string(),
+            value,
+        },
+        translated_values: vec![],
+    }
+}
+
+fn localized_label(label: &str) -> LocalizedString {
+    LocalizedString {
+        default_value: TranslatedString {
+            language: "en".to_string(),
+            value: label.to_string(),
+        },
+        translated_values: PASS_LANGUAGES[1..].iter().filter_map(|lang| {
+            translate_label(lang, label).map(|value| TranslatedString {
+                language: lang.to_string(),
+                value,
+            })
+        }).collect(),
+    }
+}
+
+fn localized_value(field: &PassField) -> Option<LocalizedString> {
+    if field.value_translations.is_empty() {
+        return None;
+    }
+    let mut value = untranslated(field_value(field));
+    value.translated_values = field.value_translations.iter().map(|(lang, v)| TranslatedString {
+        language: lang.clone(),
+        value: v.clone(),
+    }).collect();
+    Some(value)
+}
+
+/// Object IDs may only contain alphanumerics, `.`, `_` and `-`.
+fn object_id_suffix(serial: &str) -> String {
+    serial.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+fn hex_colour(colour: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.0, colour.1, colour.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{decode_hc1, DecodeMode};
+    use crate::test_support::{self, KeyIdLocation};
+
+    fn signing_key() -> GoogleWalletSigningKey {
+        GoogleWalletSigningKey {
+            issuer_id: "3388000000012345678".to_string(),
+            class_suffix: "covid".to_string(),
+            service_account_email: "wallet@example.iam.gserviceaccount.com".to_string(),
+            private_key: openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap(),
+        }
+    }
+
+    fn vaccination_pass() -> Pass {
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", 1622316073, test_support::vaccination(2)),
+            &test_support::signing_key(), b"kid", KeyIdLocation::Protected,
+        );
+        let hc1 = decode_hc1(&data, DecodeMode::Strict).unwrap();
+        crate::pass::ehealth_payload_to_pass(hc1.payload, data).unwrap()
+    }
+
+    fn decode_part(part: &str) -> serde_json::Value {
+        serde_json::from_slice(&base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn save_url_is_a_signed_jwt_carrying_the_object() {
+        let key = signing_key();
+        let object = pass_to_generic_object(&vaccination_pass(), &key).unwrap();
+        assert_eq!(object.id, "3388000000012345678.V_01DE_IZ12345A_5CWLU12RNOB9RXSEOP6FG8_2_2");
+        let url = sign_save_url(&object, &key).unwrap();
+
+        let jwt = url.strip_prefix(SAVE_URL).unwrap();
+        let parts = jwt.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+
+        let header = decode_part(parts[0]);
+        assert_eq!(header["alg"], "RS256");
+        assert_eq!(header["typ"], "JWT");
+
+        let claims = decode_part(parts[1]);
+        assert_eq!(claims["iss"], "wallet@example.iam.gserviceaccount.com");
+        assert_eq!(claims["aud"], "google");
+        assert_eq!(claims["typ"], "savetowallet");
+        assert_eq!(claims["payload"]["genericClasses"], serde_json::json!([{"id": "3388000000012345678.covid"}]));
+        assert_eq!(claims["payload"]["genericObjects"][0], serde_json::to_value(&object).unwrap());
+
+        let public_key = openssl::pkey::PKey::public_key_from_der(&key.private_key.public_key_to_der().unwrap()).unwrap();
+        let mut verifier = openssl::sign::Verifier::new(openssl::hash::MessageDigest::sha256(), &public_key).unwrap();
+        verifier.update(format!("{}.{}", parts[0], parts[1]).as_bytes()).unwrap();
+        assert!(verifier.verify(&base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn object_has_the_pass_fields() {
+        let object = pass_to_generic_object(&vaccination_pass(), &signing_key()).unwrap();
+        assert_eq!(object.header.default_value.value, "Erika Musterfrau");
+        assert_eq!(object.hex_background_colour.as_deref(), Some("#003399"));
+        let module = |id: &str| object.text_modules.iter().find(|m| m.id == id).unwrap().body.clone();
+        assert_eq!(module("dose"), "2 of 2");
+        assert_eq!(module("dob"), "1964-08-12");
+        assert_eq!(module("dt"), "2021-05-29");
+        assert_eq!(module("exp"), "2022-05-29 19:21 +00:00");
+    }
+}
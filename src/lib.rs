@@ -0,0 +1,25 @@
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate include_dir;
+#[macro_use]
+extern crate log;
+
+pub mod error;
+pub mod valuesets;
+pub mod dcc;
+pub mod decode;
+pub mod verify;
+pub mod nzcp;
+pub mod shc;
+pub mod pass;
+pub mod pkpass;
+pub mod localisation;
+pub mod google_wallet;
+pub mod passupdates;
+#[cfg(test)]
+mod test_support;
+
+pub use error::{Error, SignatureError, TrustListError};
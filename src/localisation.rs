@@ -0,0 +1,174 @@
+use crate::pkpass::{PKPass, PKPassField};
+
+/// Languages a `<lang>.lproj/pass.strings` bundle is generated for, English first as the fallback.
+pub const PASS_LANGUAGES: [&str; 8] = ["en", "de", "fr", "es", "it", "nl", "tr", "pl"];
+
+/// Translations of the English pass labels, in the order of `PASS_LANGUAGES` after `en`.
+const LABELS: &[(&str, [&str; 7])] = &[
+    ("Code", ["Code", "Code", "Código", "Codice", "Code", "Kod", "Kod"]),
+    ("Country", ["Land", "Pays", "País", "Paese", "Land", "Ülke", "Kraj"]),
+    ("Date of Birth", [
+        "Geburtsdatum", "Date de naissance", "Fecha de nacimiento", "Data di nascita", "Geboortedatum", "Doğum tarihi",
+        "Data urodzenia",
+    ]),
+    ("Date of Vaccination", [
+        "Impfdatum", "Date de vaccination", "Fecha de vacunación", "Data della vaccinazione", "Vaccinatiedatum",
+        "Aşı tarihi", "Data szczepienia",
+    ]),
+    ("Date of first positive test", [
+        "Datum des ersten positiven Tests", "Date du premier test positif", "Fecha de la primera prueba positiva",
+        "Data del primo test positivo", "Datum eerste positieve test", "İlk pozitif test tarihi",
+        "Data pierwszego pozytywnego testu",
+    ]),
+    ("Date of result", [
+        "Ergebnisdatum", "Date du résultat", "Fecha del resultado", "Data del risultato", "Uitslagdatum",
+        "Sonuç tarihi", "Data wyniku",
+    ]),
+    ("Date of test", [
+        "Testdatum", "Date du test", "Fecha de la prueba", "Data del test", "Testdatum", "Test tarihi", "Data testu",
+    ]),
+    ("Dose", ["Dosis", "Dose", "Dosis", "Dose", "Dosis", "Doz", "Dawka"]),
+    ("Doses", ["Dosen", "Doses", "Dosis", "Dosi", "Doses", "Dozlar", "Dawki"]),
+    ("Exemption", ["Befreiung", "Exemption", "Exención", "Esenzione", "Vrijstelling", "Muafiyet", "Zwolnienie"]),
+    ("Exemption status", [
+        "Befreiungsstatus", "Statut d'exemption", "Estado de exención", "Stato di esenzione", "Vrijstellingsstatus",
+        "Muafiyet durumu", "Status zwolnienia",
+    ]),
+    ("For", ["Für", "Pour", "Para", "Per", "Voor", "Hastalık", "Dotyczy"]),
+    ("ICAO name", ["ICAO-Name", "Nom OACI", "Nombre OACI", "Nome ICAO", "ICAO-naam", "ICAO adı", "Nazwisko ICAO"]),
+    ("Issued by", [
+        "Ausgestellt von", "Délivré par", "Emitido por", "Rilasciato da", "Uitgegeven door", "Düzenleyen",
+        "Wydany przez",
+    ]),
+    ("Manufacturer", ["Hersteller", "Fabricant", "Fabricante", "Produttore", "Fabrikant", "Üretici", "Producent"]),
+    ("Name", ["Name", "Nom", "Nombre", "Nome", "Naam", "Ad Soyad", "Imię i nazwisko"]),
+    ("Name (ICAO transliteration)", [
+        "Name (ICAO-Transliteration)", "Nom (translittération OACI)", "Nombre (transliteración OACI)",
+        "Nome (traslitterazione ICAO)", "Naam (ICAO-transliteratie)", "Ad (ICAO transliterasyonu)",
+        "Imię i nazwisko (transliteracja ICAO)",
+    ]),
+    ("Product", ["Produkt", "Produit", "Producto", "Prodotto", "Product", "Ürün", "Produkt"]),
+    ("Recovery", ["Genesung", "Rétablissement", "Recuperación", "Guarigione", "Herstel", "İyileşme", "Ozdrowienie"]),
+    ("Result", ["Ergebnis", "Résultat", "Resultado", "Risultato", "Resultaat", "Sonuç", "Wynik"]),
+    ("Status", ["Status", "Statut", "Estado", "Stato", "Status", "Durum", "Status"]),
+    ("Test", ["Test", "Test", "Prueba", "Test", "Test", "Test", "Test"]),
+    ("Test centre", [
+        "Testzentrum", "Centre de test", "Centro de pruebas", "Centro di test", "Testcentrum", "Test merkezi",
+        "Punkt testowy",
+    ]),
+    ("Test name", ["Testname", "Nom du test", "Nombre de la prueba", "Nome del test", "Testnaam", "Test adı", "Nazwa testu"]),
+    ("Test type", ["Testart", "Type de test", "Tipo de prueba", "Tipo di test", "Type test", "Test türü", "Rodzaj testu"]),
+    ("Vaccination", ["Impfung", "Vaccination", "Vacunación", "Vaccinazione", "Vaccinatie", "Aşılama", "Szczepienie"]),
+    ("Vaccine", ["Impfstoff", "Vaccin", "Vacuna", "Vaccino", "Vaccin", "Aşı", "Szczepionka"]),
+    ("Valid from", [
+        "Gültig ab", "Valable à partir du", "Válido desde", "Valido dal", "Geldig vanaf", "Geçerlilik başlangıcı",
+        "Ważny od",
+    ]),
+    ("Valid until", [
+        "Gültig bis", "Valable jusqu'au", "Válido hasta", "Valido fino al", "Geldig tot", "Geçerlilik bitişi",
+        "Ważny do",
+    ]),
+    ("View certificate", [
+        "Zertifikat anzeigen", "Voir le certificat", "Ver certificado", "Visualizza certificato",
+        "Certificaat bekijken", "Sertifikayı görüntüle", "Zobacz certyfikat",
+    ]),
+];
+
+/// Translates an English pass label, also handling numbered labels such as "Vaccination 2".
+pub fn translate_label(lang: &str, label: &str) -> Option<String> {
+    let lang_index = PASS_LANGUAGES.iter().position(|l| *l == lang)?;
+    if lang_index == 0 {
+        return Some(label.to_string());
+    }
+    let lookup = |label: &str| LABELS.iter()
+        .find(|(en, _)| *en == label)
+        .map(|(_, translations)| translations[lang_index - 1].to_string());
+
+    lookup(label).or_else(|| {
+        let (prefix, number) = label.rsplit_once(' ')?;
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        lookup(prefix).map(|p| format!("{} {}", p, number))
+    })
+}
+
+/// Replaces the labels of every field with its key, and values with translations with `<key>.value`, returning the
+/// `(path, contents)` of the `pass.strings` file for each of `PASS_LANGUAGES` that maps them back to display text.
+pub fn localise_pass(pass: &mut PKPass) -> Vec<(String, Vec<u8>)> {
+    let mut strings = PASS_LANGUAGES.iter().map(|_| vec![]).collect::<Vec<Vec<(String, String)>>>();
+
+    let structure = pass.pass_style.structure_mut();
+    let fields = structure.header_fields.iter_mut()
+        .chain(structure.primary_fields.iter_mut())
+        .chain(structure.secondary_fields.iter_mut())
+        .chain(structure.aux_fields.iter_mut())
+        .chain(structure.back_fields.iter_mut());
+    for field in fields {
+        localise_field(field, &mut strings);
+    }
+
+    PASS_LANGUAGES.iter().zip(strings).map(|(lang, entries)| {
+        (format!("{}.lproj/pass.strings", lang), encode_strings(&entries))
+    }).collect()
+}
+
+fn localise_field(field: &mut PKPassField, strings: &mut [Vec<(String, String)>]) {
+    if let Some(label) = field.label.take() {
+        for (lang, entries) in PASS_LANGUAGES.iter().zip(strings.iter_mut()) {
+            let translated = translate_label(lang, &label).unwrap_or_else(|| label.clone());
+            entries.push((field.key.clone(), translated));
+        }
+        field.label = Some(field.key.clone());
+    }
+
+    if !field.value_translations.is_empty() {
+        let value_key = format!("{}.value", field.key);
+        for (lang, entries) in PASS_LANGUAGES.iter().zip(strings.iter_mut()) {
+            let translated = field.value_translations.get(*lang).unwrap_or(&field.value).clone();
+            entries.push((value_key.clone(), translated));
+        }
+        field.value = value_key;
+    }
+}
+
+/// Encodes a `.strings` file as UTF-16 with a byte order mark, as PassKit expects.
+fn encode_strings(entries: &[(String, String)]) -> Vec<u8> {
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    let contents = entries.iter()
+        .map(|(k, v)| format!("\"{}\" = \"{}\";\n", escape(k), escape(v)))
+        .collect::<String>();
+
+    let mut out = vec![0xFF, 0xFE];
+    for unit in contents.encode_utf16() {
+        out.extend_from_slice(&unit.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_numbered_labels() {
+        assert_eq!(translate_label("de", "Country").as_deref(), Some("Land"));
+        assert_eq!(translate_label("fr", "Code 2").as_deref(), Some("Code 2"));
+        assert_eq!(translate_label("de", "View certificate 12").as_deref(), Some("Zertifikat anzeigen 12"));
+        assert_eq!(translate_label("en", "Anything 2").as_deref(), Some("Anything 2"));
+        assert_eq!(translate_label("de", "Country A"), None);
+        assert_eq!(translate_label("de", "Unknown 2"), None);
+        assert_eq!(translate_label("ja", "Country"), None);
+    }
+
+    #[test]
+    fn encodes_strings_as_utf16_with_a_byte_order_mark() {
+        let encoded = encode_strings(&[
+            ("name".to_string(), "Ülke \"A\"\\B\nC".to_string()),
+        ]);
+        assert_eq!(&encoded[..2], &[0xFF, 0xFE]);
+
+        let units = encoded[2..].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>();
+        assert_eq!(String::from_utf16(&units).unwrap(), "\"name\" = \"Ülke \\\"A\\\"\\\\B\\nC\";\n");
+    }
+}
@@ -1,750 +1,176 @@
-#![feature(decl_macro)]
-
 #[macro_use]
 extern crate rocket;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
-#[macro_use]
-extern crate include_dir;
-
-use coset::TaggedCborSerializable;
-use chrono::prelude::*;
-use serde::Deserializer;
-use std::fmt::Formatter;
-use std::io::Write;
-use std::io::Read;
-
-const VALUE_SET_COUNTRY_CODE_STR: &'static str = include_str!("../eu-dcc-valuesets/country-2-codes.json");
-const VALUE_SET_DISEASE_STR: &'static str = include_str!("../eu-dcc-valuesets/disease-agent-targeted.json");
-const VALUE_SET_TEST_MANUFACTURER_STR: &'static str = include_str!("../eu-dcc-valuesets/test-manf.json");
-const VALUE_SET_TEST_RESULT_STR: &'static str = include_str!("../eu-dcc-valuesets/test-result.json");
-const VALUE_SET_TEST_TYPE_STR: &'static str = include_str!("../eu-dcc-valuesets/test-type.json");
-const VALUE_SET_VACCINE_MANUFACTURER_STR: &'static str = include_str!("../eu-dcc-valuesets/vaccine-mah-manf.json");
-const VALUE_SET_VACCINE_PRODUCT_STR: &'static str = include_str!("../eu-dcc-valuesets/vaccine-medicinal-product.json");
-const VALUE_SET_VACCINE_PROPHYLAXIS_STR: &'static str = include_str!("../eu-dcc-valuesets/vaccine-prophylaxis.json");
-
-//const UK_CERT_URL: &'static str = "https://covid-status.service.nhsx.nhs.uk/pubkeys/keys.json";
 
-const PASS_TYPE_ID: &'static str = "pass.ch.magicalcodewit.pass.covid";
-const PASS_TEAM_ID: &'static str = "MQ9TN9772U";
-
-const PASS_ASSETS: include_dir::Dir = include_dir!("./pass-assets");
-
-const VERIFIABLE_COUNTRIES: [&'static str; 0] = [];
-
-#[derive(Debug, Deserialize)]
-struct ValueSet {
-    #[serde(rename = "valueSetId")]
-    id: String,
-    #[serde(rename = "valueSetDate")]
-    date: String,
-    #[serde(rename = "valueSetValues")]
-    values: std::collections::HashMap<String, ValueSetValue>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-struct ValueSetValue {
-    display: String,
-    lang: String,
-    active: bool,
-    version: String,
-    system: String,
-}
+use ehealth_apple_wallet::{decode, google_wallet, nzcp, pass, passupdates, pkpass, shc, valuesets, verify, Error};
+use ehealth_apple_wallet::verify::{PassSigningCerts, TrustListSource, ValidityPolicy};
+use ehealth_apple_wallet::pkpass::PKPassSigningKeys;
 
 lazy_static! {
-    static ref VALUE_SET_COUNTRY_CODE: ValueSet = serde_json::from_str(VALUE_SET_COUNTRY_CODE_STR).unwrap();
-    static ref VALUE_SET_DISEASE: ValueSet = serde_json::from_str(VALUE_SET_DISEASE_STR).unwrap();
-    static ref VALUE_SET_TEST_MANUFACTURER: ValueSet = serde_json::from_str(VALUE_SET_TEST_MANUFACTURER_STR).unwrap();
-    static ref VALUE_SET_TEST_RESULT: ValueSet = serde_json::from_str(VALUE_SET_TEST_RESULT_STR).unwrap();
-    static ref VALUE_SET_TEST_TYPE: ValueSet = serde_json::from_str(VALUE_SET_TEST_TYPE_STR).unwrap();
-    static ref VALUE_SET_VACCINE_MANUFACTURER: ValueSet = serde_json::from_str(VALUE_SET_VACCINE_MANUFACTURER_STR).unwrap();
-    static ref VALUE_SET_VACCINE_PRODUCT: ValueSet = serde_json::from_str(VALUE_SET_VACCINE_PRODUCT_STR).unwrap();
-    static ref VALUE_SET_VACCINE_PROPHYLAXIS: ValueSet = serde_json::from_str(VALUE_SET_VACCINE_PROPHYLAXIS_STR).unwrap();
-
     static ref TR_HES_REGEX: regex::Regex = regex::Regex::new(r"^[0-9a-f]{8}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{4}-?[0-9a-f]{12}\|[\w\d]{4}-?[\w\d]{4}-?[\w\d]{2}$").unwrap();
 }
 
-#[derive(Debug, Deserialize)]
-struct UKSigningCert {
-    #[serde(deserialize_with = "de_base64")]
-    kid: Vec<u8>,
-    #[serde(rename = "publicKey", deserialize_with = "de_base64_ec_key")]
-    public_key: openssl::ec::EcKey<openssl::pkey::Public>,
-}
-
-fn de_base64<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        base64::decode(&s).map_err(serde::de::Error::custom)
-    )
-}
-
-fn de_base64_ec_key<'de, D: serde::Deserializer<'de>>(d: D) -> Result<openssl::ec::EcKey<openssl::pkey::Public>, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d)
-        .and_then(|s| base64::decode(&s).map_err(serde::de::Error::custom))
-        .and_then(|c| openssl::ec::EcKey::public_key_from_der(&c).map_err(serde::de::Error::custom))
-}
-
-#[derive(Debug)]
-struct PassSigningCerts(std::collections::HashMap<PassSigningCertKey, PassSigningCert>);
-
-#[derive(Debug, Hash, Eq, PartialEq)]
-struct PassSigningCertKey {
-    kid: Vec<u8>,
-    iss: String,
-}
-
-#[derive(Debug)]
-struct PassSigningCert {
-    pkey: openssl::pkey::PKey<openssl::pkey::Public>,
-}
+const SHC_SESSION_TTL: u64 = 300;
+const MAX_BUNDLE_PASSES: usize = 10;
 
-#[derive(Debug, Serialize)]
-struct PKPass {
-    description: String,
-    #[serde(rename = "formatVersion")]
-    format_version: u32,
-    #[serde(rename = "organizationName")]
-    org_name: String,
-    #[serde(rename = "passTypeIdentifier")]
-    type_id: String,
-    #[serde(rename = "serialNumber")]
-    serial: String,
-    #[serde(rename = "teamIdentifier")]
-    team_id: String,
-    #[serde(rename = "expirationDate", skip_serializing_if = "Option::is_none")]
-    exp_date: Option<DateTime<Utc>>,
+#[derive(Debug, Deserialize)]
+struct TrustConfig {
     #[serde(default)]
-    voided: bool,
-    #[serde(flatten)]
-    pass_style: PKPassStyle,
-    #[serde(rename = "backgroundColor", skip_serializing_if = "Option::is_none")]
-    bg_colour: Option<String>,
-    #[serde(rename = "foregroundColor", skip_serializing_if = "Option::is_none")]
-    fg_colour: Option<String>,
-    #[serde(rename = "labelColor", skip_serializing_if = "Option::is_none")]
-    label_colour: Option<String>,
-    #[serde(rename = "logoText", skip_serializing_if = "Option::is_none")]
-    logo_text: Option<String>,
-    #[serde(rename = "webServiceURL", skip_serializing_if = "Option::is_none")]
-    web_service_url: Option<String>,
-    #[serde(rename = "authenticationToken", skip_serializing_if = "Option::is_none")]
-    authentication_token: Option<String>,
-    #[serde(rename = "sharingProhibited", skip_serializing_if = "Option::is_none")]
-    sharing_prohibited: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    barcode: Option<PKPassBarcode>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    barcodes: Vec<PKPassBarcode>,
-}
-
-#[derive(Debug, Serialize)]
-#[allow(unused)]
-enum PKPassStyle {
-    #[serde(rename = "boardingPass")]
-    BoardingPass(PKPassStructure),
-    #[serde(rename = "coupon")]
-    Coupon(PKPassStructure),
-    #[serde(rename = "eventTicket")]
-    EventTicket(PKPassStructure),
-    #[serde(rename = "generic")]
-    Generic(PKPassStructure),
-    #[serde(rename = "storeCard")]
-    StoreCard(PKPassStructure),
-}
-
-#[derive(Debug, Serialize)]
-struct PKPassStructure {
-    #[serde(rename = "auxiliaryFields", skip_serializing_if = "Vec::is_empty")]
-    aux_fields: Vec<PKPassField>,
-    #[serde(rename = "backFields", skip_serializing_if = "Vec::is_empty")]
-    back_fields: Vec<PKPassField>,
-    #[serde(rename = "headerFields", skip_serializing_if = "Vec::is_empty")]
-    header_fields: Vec<PKPassField>,
-    #[serde(rename = "primaryFields", skip_serializing_if = "Vec::is_empty")]
-    primary_fields: Vec<PKPassField>,
-    #[serde(rename = "secondaryFields", skip_serializing_if = "Vec::is_empty")]
-    secondary_fields: Vec<PKPassField>,
-}
-
-#[derive(Debug, Serialize, Default)]
-struct PKPassField {
-    #[serde(rename = "attributedValue", skip_serializing_if = "Option::is_none")]
-    attributed_value: Option<String>,
-    #[serde(rename = "changeMessage", skip_serializing_if = "Option::is_none")]
-    change_message: Option<String>,
-    #[serde(rename = "dataDetectorTypes", skip_serializing_if = "Option::is_none")]
-    data_detectors: Option<Vec<PKDataDetector>>,
-    #[serde(rename = "textAlignment", skip_serializing_if = "Option::is_none")]
-    text_alignment: Option<PKTextAlignment>,
-    key: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    label: Option<String>,
-    value: String,
-    #[serde(rename = "dateStyle", skip_serializing_if = "Option::is_none")]
-    date_style: Option<PKDateStyle>,
-    #[serde(rename = "timeStyle", skip_serializing_if = "Option::is_none")]
-    time_style: Option<PKDateStyle>,
-    #[serde(rename = "numberStyle", skip_serializing_if = "Option::is_none")]
-    number_style: Option<PKNumberStyle>,
-    #[serde(rename = "ignoresTimeZone", skip_serializing_if = "Option::is_none")]
-    ignores_time_zone: Option<bool>,
-    #[serde(rename = "isRelative", skip_serializing_if = "Option::is_none")]
-    is_relative: Option<bool>,
-    #[serde(rename = "currencyCode", skip_serializing_if = "Option::is_none")]
-    currency_code: Option<String>,
-}
-
-#[derive(Debug, Serialize, Clone)]
-#[allow(unused)]
-enum PKBarcodeFormat {
-    #[serde(rename = "PKBarcodeFormatQR")]
-    QR,
-    #[serde(rename = "PKBarcodeFormatPDF417")]
-    PDF417,
-    #[serde(rename = "PKBarcodeFormatAztec")]
-    Aztec,
-    #[serde(rename = "PKBarcodeFormatCode128")]
-    Code128,
-}
-
-#[derive(Debug, Serialize, Clone)]
-#[allow(unused)]
-enum PKDataDetector {
-    #[serde(rename = "PKDataDetectorTypePhoneNumber")]
-    PhoneNumber,
-    #[serde(rename = "PKDataDetectorTypeLink")]
-    Link,
-    #[serde(rename = "PKDataDetectorTypeAddress")]
-    Address,
-    #[serde(rename = "PKDataDetectorTypeCalendarEvent")]
-    CalendarEvent,
+    dcc_trust_list: Option<String>,
+    #[serde(default)]
+    csca_trust_list: Option<String>,
+    #[serde(default)]
+    uk_trust_list: Option<String>,
+    #[serde(default)]
+    uk_trust_list_url: Option<String>,
+    #[serde(default)]
+    revocation_list_dir: Option<String>,
+    #[serde(default)]
+    shc_issuer_dir: Option<String>,
+    #[serde(default)]
+    nzcp_did_dir: Option<String>,
+    #[serde(default)]
+    trust_list_refresh_interval: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[allow(unused)]
-enum PKTextAlignment {
-    #[serde(rename = "PKTextAlignmentLeft")]
-    Left,
-    #[serde(rename = "PKTextAlignmentCenter")]
-    Center,
-    #[serde(rename = "PKTextAlignmentRight")]
-    Right,
-    #[serde(rename = "PKTextAlignmentNatural")]
-    Natural,
+#[derive(Debug, Deserialize)]
+struct DecodeConfig {
+    #[serde(default)]
+    decode_mode: decode::DecodeMode,
+    #[serde(default)]
+    validity_policy: ValidityPolicy,
+    #[serde(default)]
+    value_set_dir: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[allow(unused)]
-enum PKDateStyle {
-    #[serde(rename = "PKDateStyleNone")]
-    None,
-    #[serde(rename = "PKDateStyleShort")]
-    Short,
-    #[serde(rename = "PKDateStyleMedium")]
-    Medium,
-    #[serde(rename = "PKDateStyleLong")]
-    Long,
-    #[serde(rename = "PKDateStyleFull")]
-    Full,
+#[derive(Debug, Deserialize)]
+struct GoogleWalletConfig {
+    #[serde(default)]
+    google_wallet_issuer_id: Option<String>,
+    #[serde(default)]
+    google_wallet_service_account: Option<String>,
+    #[serde(default = "default_google_wallet_class")]
+    google_wallet_class: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
-#[allow(unused)]
-enum PKNumberStyle {
-    #[serde(rename = "PKNumberStyleDecimal")]
-    Decimal,
-    #[serde(rename = "PKNumberStylePercent")]
-    Percent,
-    #[serde(rename = "PKNumberStyleScientific")]
-    Scientific,
-    #[serde(rename = "PKNumberStyleSpellOut")]
-    SpellOut,
+fn default_google_wallet_class() -> String {
+    "ehealth".to_string()
 }
 
-#[derive(Debug, Serialize, Clone)]
-struct PKPassBarcode {
-    #[serde(rename = "altText", skip_serializing_if = "Option::is_none")]
-    alt_text: Option<String>,
-    format: PKBarcodeFormat,
-    message: String,
-    #[serde(rename = "messageEncoding")]
-    message_encoding: String,
+#[derive(Debug, Deserialize)]
+struct PassUpdateConfig {
+    #[serde(default)]
+    pass_update_db: Option<String>,
+    #[serde(default)]
+    pass_update_db_key: Option<String>,
+    #[serde(default)]
+    pass_web_service_url: Option<String>,
 }
 
-fn strip_uvci(uvci: &str) -> &str {
-    let bare_uvci_with_checksum = uvci.strip_prefix("URN:UVCI:").unwrap_or(uvci);
-    bare_uvci_with_checksum.rsplit_once("#").map(|(a, _b)| a).unwrap_or(bare_uvci_with_checksum)
+#[derive(Debug, Clone, Copy)]
+struct PassOptions {
+    decode_mode: decode::DecodeMode,
+    validity_policy: ValidityPolicy,
 }
 
-fn ehealth_payload_to_pkpass(payload: EHealthPayload, msg: String) -> Result<PKPass, &'static str> {
-    let serial = match &payload.hcert.eu_dgc_v1.group {
-        EUDigitalGreenCertGroup::Vaccination(v) => match v.first() {
-            Some(d) => format!("V:{}:{}:{}", strip_uvci(&d.id), d.dose, d.series),
-            None => return Err("invalid payload")
-        },
-        EUDigitalGreenCertGroup::Test(t) => match t.first() {
-            Some(d) => format!("T:{}", strip_uvci(&d.id)),
-            None => return Err("invalid payload")
+impl TrustConfig {
+    fn sources(&self) -> Vec<TrustListSource> {
+        let mut sources = vec![];
+        if let Some(path) = &self.dcc_trust_list {
+            sources.push(TrustListSource::DCCGateway(path.clone()));
         }
-        EUDigitalGreenCertGroup::Recovery(r) => match r.first() {
-            Some(d) => format!("R:{}", strip_uvci(&d.id)),
-            None => return Err("invalid payload")
+        if let Some(path) = &self.csca_trust_list {
+            sources.push(TrustListSource::CSCAList(path.clone()));
         }
-    };
-
-    let disease = match &payload.hcert.eu_dgc_v1.group {
-        EUDigitalGreenCertGroup::Vaccination(v) => match v.first() {
-            Some(d) => d.targeted.display.clone(),
-            None => unreachable!()
-        },
-        EUDigitalGreenCertGroup::Test(t) => match t.first() {
-            Some(d) => d.targeted.display.clone(),
-            None => unreachable!()
-        }
-        EUDigitalGreenCertGroup::Recovery(r) => match r.first() {
-            Some(d) => d.targeted.display.clone(),
-            None => unreachable!()
+        if let Some(path) = &self.uk_trust_list {
+            sources.push(TrustListSource::UKFile(path.clone()));
         }
-    };
-
-    let issued_by = match &payload.hcert.eu_dgc_v1.group {
-        EUDigitalGreenCertGroup::Vaccination(v) => match v.first() {
-            Some(d) => d.issuer.clone(),
-            None => unreachable!()
-        },
-        EUDigitalGreenCertGroup::Test(t) => match t.first() {
-            Some(d) => d.issuer.clone(),
-            None => unreachable!()
+        if let Some(url) = &self.uk_trust_list_url {
+            sources.push(TrustListSource::UKUrl(url.clone()));
         }
-        EUDigitalGreenCertGroup::Recovery(r) => match r.first() {
-            Some(d) => d.issuer.clone(),
-            None => unreachable!()
+        if let Some(path) = &self.revocation_list_dir {
+            sources.push(TrustListSource::RevocationDir(path.clone()));
         }
-    };
-
-    let barcode = PKPassBarcode {
-        alt_text: None,
-        format: PKBarcodeFormat::QR,
-        message: msg,
-        message_encoding: "iso-8859-1".to_string(),
-    };
-
-    let mut aux_fields = vec![];
-    let mut back_fields = vec![PKPassField {
-        data_detectors: Some(vec![]),
-        key: "exp".to_string(),
-        label: Some("Valid until".to_string()),
-        value: payload.exp.to_rfc3339(),
-        date_style: Some(PKDateStyle::Long),
-        time_style: Some(PKDateStyle::Long),
-        ..Default::default()
-    }, PKPassField {
-        data_detectors: Some(vec![]),
-        key: "iss".to_string(),
-        label: Some("Issued by".to_string()),
-        value: issued_by.clone(),
-        ..Default::default()
-    }];
-    let mut secondary_fields = vec![PKPassField {
-        data_detectors: Some(vec![]),
-        key: "dob".to_string(),
-        label: Some("Date of Birth".to_string()),
-        value: Utc.from_utc_date(&payload.hcert.eu_dgc_v1.dob).and_hms(0, 0, 0).to_rfc3339(),
-        date_style: Some(PKDateStyle::Long),
-        time_style: Some(PKDateStyle::None),
-        ignores_time_zone: Some(true),
-        ..Default::default()
-    }];
-
-    match &payload.hcert.eu_dgc_v1.group {
-        EUDigitalGreenCertGroup::Vaccination(v) => match v.first() {
-            Some(d) => {
-                aux_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "vc".to_string(),
-                    label: Some("Vaccine".to_string()),
-                    value: d.vaccine.display.clone(),
-                    ..Default::default()
-                });
-                secondary_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "dose".to_string(),
-                    label: Some("Dose".to_string()),
-                    value: format!("{} of {}", d.dose, d.series),
-                    ..Default::default()
-                });
-                aux_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "dt".to_string(),
-                    label: Some("Date of Vaccination".to_string()),
-                    value: Utc.from_utc_date(&d.date).and_hms(0, 0, 0).to_rfc3339(),
-                    date_style: Some(PKDateStyle::Long),
-                    time_style: Some(PKDateStyle::None),
-                    ignores_time_zone: Some(true),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "mn".to_string(),
-                    label: Some("Manufacturer".to_string()),
-                    value: d.manufacturer.display.clone(),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "pd".to_string(),
-                    label: Some("Product".to_string()),
-                    value: d.product.display.clone(),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "co".to_string(),
-                    label: Some("Country".to_string()),
-                    value: d.country.display.clone(),
-                    ..Default::default()
-                });
-            }
-            None => unreachable!()
-        },
-        EUDigitalGreenCertGroup::Test(t) => match t.first() {
-            Some(d) => {
-                secondary_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "tr".to_string(),
-                    label: Some("Result".to_string()),
-                    value: d.result.display.clone(),
-                    ..Default::default()
-                });
-                aux_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "dt".to_string(),
-                    label: Some("Date of test".to_string()),
-                    value: Utc.from_utc_date(&d.sample_date).and_hms(0, 0, 0).to_rfc3339(),
-                    date_style: Some(PKDateStyle::Long),
-                    time_style: Some(PKDateStyle::None),
-                    ignores_time_zone: Some(true),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "tt".to_string(),
-                    label: Some("Test type".to_string()),
-                    value: d.test_type.display.clone(),
-                    ..Default::default()
-                });
-                if let Some(nm) = &d.name {
-                    back_fields.push(PKPassField {
-                        data_detectors: Some(vec![]),
-                        key: "nm".to_string(),
-                        label: Some("Test name".to_string()),
-                        value: nm.clone(),
-                        ..Default::default()
-                    });
-                }
-                if let Some(tc) = &d.centre {
-                    back_fields.push(PKPassField {
-                        data_detectors: Some(vec![]),
-                        key: "tc".to_string(),
-                        label: Some("Test centre".to_string()),
-                        value: tc.clone(),
-                        ..Default::default()
-                    });
-                }
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "co".to_string(),
-                    label: Some("Country".to_string()),
-                    value: d.country.display.clone(),
-                    ..Default::default()
-                });
-            }
-            None => unreachable!()
+        if let Some(path) = &self.shc_issuer_dir {
+            sources.push(TrustListSource::SHCIssuerDir(path.clone()));
         }
-        EUDigitalGreenCertGroup::Recovery(r) => match r.first() {
-            Some(d) => {
-                aux_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "df".to_string(),
-                    label: Some("Valid from".to_string()),
-                    value: Utc.from_utc_date(&d.valid_from_date).and_hms(0, 0, 0).to_rfc3339(),
-                    date_style: Some(PKDateStyle::Long),
-                    time_style: Some(PKDateStyle::None),
-                    ignores_time_zone: Some(true),
-                    ..Default::default()
-                });
-                aux_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "du".to_string(),
-                    label: Some("Valid until".to_string()),
-                    value: Utc.from_utc_date(&d.valid_until_date).and_hms(0, 0, 0).to_rfc3339(),
-                    date_style: Some(PKDateStyle::Long),
-                    time_style: Some(PKDateStyle::None),
-                    ignores_time_zone: Some(true),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "fr".to_string(),
-                    label: Some("Date of first positive test".to_string()),
-                    date_style: Some(PKDateStyle::Long),
-                    time_style: Some(PKDateStyle::None),
-                    ignores_time_zone: Some(true),
-                    value: Utc.from_utc_date(&d.first_positive_test_date).and_hms(0, 0, 0).to_rfc3339(),
-                    ..Default::default()
-                });
-                back_fields.push(PKPassField {
-                    data_detectors: Some(vec![]),
-                    key: "co".to_string(),
-                    label: Some("Country".to_string()),
-                    value: d.country.display.clone(),
-                    ..Default::default()
-                });
-            }
-            None => unreachable!()
+        if let Some(path) = &self.nzcp_did_dir {
+            sources.push(TrustListSource::DIDDocumentDir(path.clone()));
         }
+        sources
     }
-
-
-    Ok(PKPass {
-        format_version: 1,
-        description: if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Vaccination(_)) {
-            "eHealth digital vaccination certificate".to_string()
-        } else if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Test(_)) {
-            "eHealth digital test certificate".to_string()
-        } else if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Recovery(_)) {
-            "eHealth digital recovery certificate".to_string()
-        } else {
-            "eHealth digital certificate".to_string()
-        },
-        org_name: issued_by,
-        type_id: PASS_TYPE_ID.to_string(),
-        serial,
-        team_id: PASS_TEAM_ID.to_string(),
-        voided: false,
-        sharing_prohibited: Some(true),
-        pass_style: PKPassStyle::Generic(PKPassStructure {
-            aux_fields,
-            back_fields,
-            header_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "tg".to_string(),
-                label: Some("For".to_string()),
-                value: disease,
-                ..Default::default()
-            }],
-            primary_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "fn".to_string(),
-                label: Some("Name".to_string()),
-                value: format!("{} {}", payload.hcert.eu_dgc_v1.name.forename, payload.hcert.eu_dgc_v1.name.surname),
-                ..Default::default()
-            }],
-            secondary_fields,
-        }),
-        bg_colour: Some("rgb(0, 51, 153)".to_string()),
-        fg_colour: Some("rgb(255, 255, 255)".to_string()),
-        label_colour: Some("rgb(255, 204, 0)".to_string()),
-        logo_text: Some(if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Vaccination(_)) {
-            "Vaccination".to_string()
-        } else if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Test(_)) {
-            "Test".to_string()
-        } else if matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Recovery(_)) {
-            "Recovery".to_string()
-        } else {
-            "Certificate".to_string()
-        }),
-        web_service_url: None,
-        authentication_token: None,
-        exp_date: Some(payload.exp),
-        barcode: Some(barcode.clone()),
-        barcodes: vec![barcode],
-    })
 }
 
-fn turkey_payload_to_pkpass(msg: String) -> Result<PKPass, &'static str> {
-    let serial = match msg.strip_prefix("https://covidasidogrulama.saglik.gov.tr/api/CovidAsiKartiDogrula?Guid=") {
-        Some(s) => s,
-        None => return Err("invalid payload")
-    };
-
-    let barcode = PKPassBarcode {
-        alt_text: None,
-        format: PKBarcodeFormat::QR,
-        message: msg.clone(),
-        message_encoding: "iso-8859-1".to_string(),
-    };
-
-    Ok(PKPass {
-        format_version: 1,
-        description: "Turkey vaccination certificate".to_string(),
-        org_name: "Government of Turkey".to_string(),
-        type_id: PASS_TYPE_ID.to_string(),
-        serial: serial.to_string(),
-        team_id: PASS_TEAM_ID.to_string(),
-        voided: false,
-        sharing_prohibited: Some(true),
-        pass_style: PKPassStyle::Generic(PKPassStructure {
-            aux_fields: vec![],
-            back_fields: vec![PKPassField {
-                data_detectors: Some(vec![
-                    PKDataDetector::Link
-                ]),
-                key: "vc".to_string(),
-                label: Some("View certificate".to_string()),
-                value: msg,
-                ..Default::default()
-            }],
-            header_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "tg".to_string(),
-                label: Some("For".to_string()),
-                value: "COVID-19".to_string(),
-                ..Default::default()
-            }],
-            primary_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "iss".to_string(),
-                label: Some("Issued by".to_string()),
-                value: "Government of Turkey".to_string(),
-                ..Default::default()
-            }],
-            secondary_fields: vec![],
-        }),
-        bg_colour: Some("rgb(185, 232, 234)".to_string()),
-        fg_colour: Some("rgb(0, 0, 0)".to_string()),
-        label_colour: Some("rgb(27, 182, 193)".to_string()),
-        logo_text: Some("Vaccination".to_string()),
-        web_service_url: None,
-        authentication_token: None,
-        exp_date: None,
-        barcode: Some(barcode.clone()),
-        barcodes: vec![barcode],
-    })
+#[derive(Clone)]
+struct TrustStore {
+    sources: std::sync::Arc<Vec<TrustListSource>>,
+    signing_certs: std::sync::Arc<std::sync::RwLock<std::sync::Arc<PassSigningCerts>>>,
 }
 
-fn turkey_hes_payload_to_pkpass(msg: String) -> Result<PKPass, &'static str> {
-    let hes_code = match msg.split_once("|") {
-        Some(s) => s.1,
-        None => return Err("invalid payload")
-    };
+impl TrustStore {
+    async fn new(sources: Vec<TrustListSource>) -> Result<Self, ehealth_apple_wallet::TrustListError> {
+        let signing_certs = verify::load_trust_lists(&sources).await?;
+        Ok(TrustStore {
+            sources: std::sync::Arc::new(sources),
+            signing_certs: std::sync::Arc::new(std::sync::RwLock::new(std::sync::Arc::new(signing_certs))),
+        })
+    }
 
-    let barcode = PKPassBarcode {
-        alt_text: None,
-        format: PKBarcodeFormat::QR,
-        message: msg.clone(),
-        message_encoding: "iso-8859-1".to_string(),
-    };
+    fn current(&self) -> std::sync::Arc<PassSigningCerts> {
+        self.signing_certs.read().unwrap().clone()
+    }
 
-    Ok(PKPass {
-        format_version: 1,
-        description: "Turkey HES certificate".to_string(),
-        org_name: "Government of Turkey".to_string(),
-        type_id: PASS_TYPE_ID.to_string(),
-        serial: hes_code.to_string(),
-        team_id: PASS_TEAM_ID.to_string(),
-        voided: false,
-        sharing_prohibited: Some(true),
-        pass_style: PKPassStyle::Generic(PKPassStructure {
-            aux_fields: vec![],
-            back_fields: vec![],
-            header_fields: vec![],
-            primary_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "hes".to_string(),
-                label: Some("Code".to_string()),
-                value: format!("{}-{}-{}", &hes_code[0..4], &hes_code[4..8], &hes_code[8..]),
-                ..Default::default()
-            }],
-            secondary_fields: vec![PKPassField {
-                data_detectors: Some(vec![]),
-                key: "iss".to_string(),
-                label: Some("Issued by".to_string()),
-                value: "Government of Turkey".to_string(),
-                ..Default::default()
-            }],
-        }),
-        bg_colour: Some("rgb(90, 168, 0)".to_string()),
-        fg_colour: Some("rgb(255, 255, 255)".to_string()),
-        label_colour: Some("rgb(255, 87, 34)".to_string()),
-        logo_text: Some("HES Code".to_string()),
-        web_service_url: None,
-        authentication_token: None,
-        exp_date: None,
-        barcode: Some(barcode.clone()),
-        barcodes: vec![barcode],
-    })
-}
+    fn spawn_refresh(
+        &self, refresh_interval: std::time::Duration, pass_updates: Option<passupdates::PassUpdateService>,
+    ) {
+        let trust_store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                trust_store.reload(pass_updates.as_ref()).await;
+            }
+        });
+    }
 
-struct PKPassSigningKeys {
-    public_cert: openssl::x509::X509,
-    private_key: openssl::pkey::PKey<openssl::pkey::Private>,
-    intermediate_certs: openssl::stack::Stack<openssl::x509::X509>,
+    async fn reload(&self, pass_updates: Option<&passupdates::PassUpdateService>) {
+        match verify::load_trust_lists(&self.sources).await {
+            Ok(signing_certs) => {
+                info!("Reloaded trust lists, {} signing keys now trusted", signing_certs.signing_key_count());
+                let signing_certs = std::sync::Arc::new(signing_certs);
+                *self.signing_certs.write().unwrap() = signing_certs.clone();
+                if let Some(pass_updates) = pass_updates {
+                    void_revoked(pass_updates.clone(), signing_certs).await;
+                }
+            }
+            Err(e) => {
+                warn!("Unable to reload trust lists, keeping previous signing keys: {}", e);
+            }
+        }
+    }
 }
 
-fn sign_pkpass(pass: &PKPass, signing_keys: &PKPassSigningKeys) -> Result<Vec<u8>, String> {
-    let pass_bytes = serde_json::to_vec(pass).map_err(|e| format!("Unable to serialize pass: {}", e))?;
-
-    let mut manifest = std::collections::HashMap::<String, String>::new();
-
-    let mut buf = vec![];
-    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(&mut buf));
-
-    archive.start_file("pass.json", zip::write::FileOptions::default())
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-    archive.write(&pass_bytes)
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-
-    let pass_hash = hex::encode(
-        openssl::hash::hash(openssl::hash::MessageDigest::sha1(), &pass_bytes)
-            .map_err(|e| format!("Failed to calculate manifest: {}", e))?
-    );
-    manifest.insert("pass.json".to_string(), pass_hash);
-
-    for file in PASS_ASSETS.files() {
-        let file_path = file.path().to_string_lossy();
-        let file_contents = file.contents();
-        archive.start_file(file_path.to_string(), zip::write::FileOptions::default())
-            .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-        archive.write(file_contents)
-            .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-
-        let file_hash = hex::encode(
-            openssl::hash::hash(openssl::hash::MessageDigest::sha1(), file_contents)
-                .map_err(|e| format!("Failed to calculate manifest: {}", e))?
-        );
-        manifest.insert(file_path.to_string(), file_hash);
+/// The pass update store is a blocking SQLCipher database, so voiding runs off the async workers.
+async fn void_revoked(pass_updates: passupdates::PassUpdateService, signing_certs: std::sync::Arc<PassSigningCerts>) {
+    match tokio::task::spawn_blocking(move || pass_updates.void_revoked(&signing_certs)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Unable to void revoked passes: {}", e),
+        Err(e) => warn!("Unable to void revoked passes: {}", e),
     }
+}
 
-    let manifest_bytes = serde_json::to_vec(&manifest).map_err(|e| format!("Unable to serialize manifest: {}", e))?;
-
-    archive.start_file("manifest.json", zip::write::FileOptions::default())
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-    archive.write(&manifest_bytes)
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-
-    let pkcs7 = openssl::pkcs7::Pkcs7::sign(
-        signing_keys.public_cert.as_ref(),
-        signing_keys.private_key.as_ref(),
-        signing_keys.intermediate_certs.as_ref(),
-        &manifest_bytes,
-        openssl::pkcs7::Pkcs7Flags::DETACHED | openssl::pkcs7::Pkcs7Flags::NOCRL,
-    ).map_err(|e| format!("Unable to sign manifest: {}", e))?;
-    let pkcs7_bytes = pkcs7.to_der().map_err(|e| format!("Unable to serialize signature: {}", e))?;
-
-    archive.start_file("signature", zip::write::FileOptions::default())
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-    archive.write(&pkcs7_bytes)
-        .map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-
-    archive.finish().map_err(|e| format!("Failed to write ZIP file: {}", e))?;
-    std::mem::drop(archive);
-
-    Ok(buf)
+fn spawn_shc_session_expiry(shc_assembler: shc::ShcChunkAssembler) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            shc_assembler.remove_expired();
+        }
+    });
 }
 
 struct PKPassResponse(Vec<u8>);
@@ -759,497 +185,463 @@ impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for PKPassResponse {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct EUDigitalGreenCertName {
-    #[serde(rename = "fn")]
-    surname: String,
-    #[serde(rename = "fnt")]
-    std_surname: String,
-    #[serde(rename = "gn")]
-    forename: String,
-    #[serde(rename = "gnt")]
-    std_forname: String,
-}
+struct PKPassesResponse(Vec<u8>);
 
-#[derive(Debug, Deserialize)]
-struct EUDigitalGreenCertV1 {
-    ver: String,
-    #[serde(rename = "nam")]
-    name: EUDigitalGreenCertName,
-    #[serde(deserialize_with = "de_date")]
-    dob: NaiveDate,
-    #[serde(flatten)]
-    group: EUDigitalGreenCertGroup,
-}
-
-#[derive(Debug, Deserialize)]
-enum EUDigitalGreenCertGroup {
-    #[serde(rename = "v")]
-    Vaccination(Vec<EUDigitalGreenCertVaccination>),
-    #[serde(rename = "t")]
-    Test(Vec<EUDigitalGreenCertTest>),
-    #[serde(rename = "r")]
-    Recovery(Vec<EUDigitalGreenCertRecovery>),
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for PKPassesResponse {
+    fn respond_to(self, _req: &'r rocket::request::Request<'_>) -> rocket::response::Result<'o> {
+        rocket::Response::build()
+            .header(rocket::http::ContentType::new("application", "vnd.apple.pkpasses"))
+            .raw_header("Content-Disposition", "attachment; filename=\"ehealth.pkpasses\"")
+            .sized_body(self.0.len(), std::io::Cursor::new(self.0))
+            .ok()
+    }
 }
 
-fn de_value_set_disease<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_DISEASE.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown disease: {}", s)))
-            .map(|v| v.clone())
-    )
+// Built once per request and returned straight away, so the size of the redirect doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+enum WalletResponse {
+    PKPass(PKPassResponse),
+    GoogleWallet(rocket::response::Redirect),
 }
 
-fn de_value_set_vaccine<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_VACCINE_PROPHYLAXIS.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown vaccine: {}", s)))
-            .map(|v| v.clone())
-    )
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WalletPlatform {
+    Apple,
+    Google,
 }
 
-fn de_value_set_vaccine_product<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_VACCINE_PRODUCT.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown vaccine product: {}", s)))
-            .map(|v| v.clone())
-    )
-}
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for WalletPlatform {
+    type Error = std::convert::Infallible;
 
-fn de_value_set_vaccine_manufacturer<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_VACCINE_MANUFACTURER.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown vaccine manufacturer: {}", s)))
-            .map(|v| v.clone())
-    )
+    async fn from_request(req: &'r rocket::request::Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        let platform = match req.query_value::<&str>("wallet") {
+            Some(Ok("google")) => WalletPlatform::Google,
+            Some(Ok("apple")) => WalletPlatform::Apple,
+            _ => match req.headers().get_one("User-Agent") {
+                Some(ua) if ua.contains("Android") => WalletPlatform::Google,
+                _ => WalletPlatform::Apple
+            }
+        };
+        rocket::request::Outcome::Success(platform)
+    }
 }
 
-fn de_value_set_test_type<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_TEST_TYPE.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown test type: {}", s)))
-            .map(|v| v.clone())
-    )
-}
+struct PassIssuer {
+    signing_keys: PKPassSigningKeys,
+    google_wallet_key: Option<google_wallet::GoogleWalletSigningKey>,
+    pass_updates: Option<passupdates::PassUpdateService>,
+}
+
+impl PassIssuer {
+    fn issue(
+        &self,
+        pass: pass::Pass,
+        update_metadata: Option<passupdates::PassUpdateMetadata>,
+        platform: WalletPlatform,
+    ) -> Result<WalletResponse, Error> {
+        match (platform, &self.google_wallet_key) {
+            (WalletPlatform::Google, Some(google_wallet_key)) => {
+                let object = google_wallet::pass_to_generic_object(&pass, google_wallet_key)?;
+                let url = google_wallet::sign_save_url(&object, google_wallet_key)?;
+                Ok(WalletResponse::GoogleWallet(rocket::response::Redirect::to(url)))
+            }
+            _ => Ok(WalletResponse::PKPass(PKPassResponse(self.sign_pkpass(pass, update_metadata)?)))
+        }
+    }
 
-fn de_value_set_test_result<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_TEST_RESULT.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown test result: {}", s)))
-            .map(|v| v.clone())
-    )
+    fn sign_pkpass(
+        &self,
+        pass: pass::Pass,
+        update_metadata: Option<passupdates::PassUpdateMetadata>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut pass = pkpass::pass_to_pkpass(&pass);
+        if let (Some(pass_updates), Some(update_metadata)) = (&self.pass_updates, update_metadata) {
+            pass_updates.register_pass(&mut pass, update_metadata)?;
+        }
+        pkpass::sign_pkpass(&pass, &self.signing_keys)
+    }
 }
 
-fn de_value_set_country<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        VALUE_SET_COUNTRY_CODE.values.get(&s)
-            .ok_or_else(|| serde::de::Error::custom(format!("Unknown country: {}", s)))
-            .map(|v| v.clone())
-    )
+#[get("/")]
+fn index() -> rocket_dyn_templates::Template {
+    rocket_dyn_templates::Template::render("index", std::collections::HashMap::<(), ()>::new())
 }
 
-fn de_date<'de, D: serde::Deserializer<'de>>(d: D) -> Result<NaiveDate, D::Error> {
-    use serde::de::Deserialize;
-    String::deserialize(d).and_then(|s|
-        NaiveDate::parse_from_str(&s, "%Y-%m-%d")
-            .map_err(serde::de::Error::custom)
-    )
+#[get("/privacy")]
+fn privacy() -> rocket_dyn_templates::Template {
+    rocket_dyn_templates::Template::render("privacy", std::collections::HashMap::<(), ()>::new())
 }
 
-#[derive(Debug, Deserialize)]
-struct EUDigitalGreenCertVaccination {
-    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
-    targeted: ValueSetValue,
-    #[serde(rename = "vp", deserialize_with = "de_value_set_vaccine")]
-    vaccine: ValueSetValue,
-    #[serde(rename = "mp", deserialize_with = "de_value_set_vaccine_product")]
-    product: ValueSetValue,
-    #[serde(rename = "ma", deserialize_with = "de_value_set_vaccine_manufacturer")]
-    manufacturer: ValueSetValue,
-    #[serde(rename = "dn")]
-    dose: usize,
-    #[serde(rename = "sd")]
-    series: usize,
-    #[serde(rename = "dt", deserialize_with = "de_date")]
-    date: NaiveDate,
-    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
-    country: ValueSetValue,
-    #[serde(rename = "is")]
-    issuer: String,
-    #[serde(rename = "ci")]
+#[derive(Debug, Serialize)]
+struct ValueSetInfo {
     id: String,
+    date: String,
+    source: String,
+    values: usize,
 }
 
-#[derive(Debug, Deserialize)]
-struct EUDigitalGreenCertTest {
-    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
-    targeted: ValueSetValue,
-    #[serde(rename = "tt", deserialize_with = "de_value_set_test_type")]
-    test_type: ValueSetValue,
-    #[serde(rename = "nm", default)]
-    name: Option<String>,
-    #[serde(rename = "ma", default)]
-    device: Option<String>,
-    #[serde(rename = "sc", deserialize_with = "de_date")]
-    sample_date: NaiveDate,
-    #[serde(rename = "tr", deserialize_with = "de_value_set_test_result")]
-    result: ValueSetValue,
-    #[serde(rename = "tc", default)]
-    centre: Option<String>,
-    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
-    country: ValueSetValue,
-    #[serde(rename = "is")]
-    issuer: String,
-    #[serde(rename = "ci")]
-    id: String,
+#[derive(Debug, Serialize)]
+struct ValueSetsInfo {
+    value_sets: Vec<ValueSetInfo>,
+    unknown_codes_seen: usize,
+}
+
+#[get("/value-sets")]
+fn value_sets() -> rocket::serde::json::Json<ValueSetsInfo> {
+    let value_sets = valuesets::current();
+    rocket::serde::json::Json(ValueSetsInfo {
+        value_sets: value_sets.all().iter().map(|v| ValueSetInfo {
+            id: v.id.clone(),
+            date: v.date.clone(),
+            source: match &v.source {
+                Some(p) => p.display().to_string(),
+                None => "compiled in".to_string()
+            },
+            values: v.values.len(),
+        }).collect(),
+        unknown_codes_seen: valuesets::unknown_value_set_code_count(),
+    })
 }
 
-#[derive(Debug, Deserialize)]
-struct EUDigitalGreenCertRecovery {
-    #[serde(rename = "tg", deserialize_with = "de_value_set_disease")]
-    targeted: ValueSetValue,
-    #[serde(rename = "fr", deserialize_with = "de_date")]
-    first_positive_test_date: NaiveDate,
-    #[serde(rename = "df", deserialize_with = "de_date")]
-    valid_from_date: NaiveDate,
-    #[serde(rename = "du", deserialize_with = "de_date")]
-    valid_until_date: NaiveDate,
-    #[serde(rename = "co", deserialize_with = "de_value_set_country")]
-    country: ValueSetValue,
-    #[serde(rename = "is")]
-    issuer: String,
-    #[serde(rename = "ci")]
-    id: String,
+#[derive(Debug, Serialize)]
+struct ErrorInfo {
+    error: &'static str,
+    kind: &'static str,
+}
+
+struct ErrorResponse(Error);
+
+impl<'r> rocket::response::Responder<'r, 'static> for ErrorResponse {
+    fn respond_to(self, req: &'r rocket::request::Request<'_>) -> rocket::response::Result<'static> {
+        error!("Unable to generate pass: {}", self.0);
+        let mut response = rocket::response::Responder::respond_to(
+            rocket_dyn_templates::Template::render("error", ErrorInfo {
+                error: self.0.user_message(),
+                kind: self.0.kind(),
+            }), req,
+        )?;
+        response.set_status(
+            rocket::http::Status::from_code(self.0.http_status()).unwrap_or(rocket::http::Status::InternalServerError)
+        );
+        response.set_raw_header("X-Error-Kind", self.0.kind());
+        Ok(response)
+    }
 }
 
-#[derive(Debug)]
-struct EHealthHcert {
-    eu_dgc_v1: EUDigitalGreenCertV1,
-}
+type IssuedPass = (pass::Pass, Option<passupdates::PassUpdateMetadata>);
 
-impl<'de> serde::Deserialize<'de> for EHealthHcert {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        struct Visitor;
+fn qr_data_to_pass(d: String, trust_store: &TrustStore, options: PassOptions) -> Result<IssuedPass, Error> {
+    if d.starts_with("HC1:") {
+        let hc1 = decode::decode_hc1(&d, options.decode_mode)?;
+        let verification = trust_store.current().verify_hc1(&hc1)?;
+        let warning = verify::check_validity(hc1.payload.nbf, Some(hc1.payload.exp), options.validity_policy)?;
+        let update_metadata = passupdates::PassUpdateMetadata::for_hc1(&hc1, verification)?;
+        let mut pass = pass::ehealth_payload_to_pass(hc1.payload, d)?;
+        if let Some(warning) = warning {
+            pass.add_warning(warning.message());
+        }
+        Ok((pass, update_metadata))
+    } else if d.starts_with("NZCP:/") {
+        let nzcp = nzcp::decode_nzcp(&d)?;
+        trust_store.current().verify_nzcp(&nzcp)?;
+        let warning = verify::check_validity(Some(nzcp.payload.nbf), Some(nzcp.payload.exp), options.validity_policy)?;
+        let mut pass = pass::nzcp_payload_to_pass(nzcp, d)?;
+        if let Some(warning) = warning {
+            pass.add_warning(warning.message());
+        }
+        Ok((pass, None))
+    } else if d.starts_with("shc:/") {
+        let card = shc::decode_shc(&d)?;
+        trust_store.current().verify_shc(&card)?;
+        let warning = verify::check_validity(card.payload.nbf, None, options.validity_policy)?;
+        let mut pass = pass::smart_health_card_to_pass(card, d)?;
+        if let Some(warning) = warning {
+            pass.add_warning(warning.message());
+        }
+        Ok((pass, None))
+    } else if d.starts_with("https://covidasidogrulama.saglik.gov.tr/api/CovidAsiKartiDogrula") {
+        Ok((pass::turkey_payload_to_pass(d)?, None))
+    } else if TR_HES_REGEX.is_match(&d) {
+        Ok((pass::turkey_hes_payload_to_pass(d)?, None))
+    } else {
+        Err(Error::Unsupported)
+    }
+}
 
-        impl<'de> serde::de::Visitor<'de> for Visitor {
-            type Value = EHealthHcert;
+#[get("/qr-data?<d>")]
+fn qr_data(
+    d: String,
+    platform: WalletPlatform,
+    trust_store: &rocket::State<TrustStore>,
+    pass_options: &rocket::State<PassOptions>,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<WalletResponse, ErrorResponse> {
+    let (pass, update_metadata) = qr_data_to_pass(d, trust_store, **pass_options).map_err(ErrorResponse)?;
+    pass_issuer.issue(pass, update_metadata, platform).map_err(ErrorResponse)
+}
+
+// Rocket's `FromForm` derive still allows the removed `private_in_public` lint on the items it generates
+#[allow(renamed_and_removed_lints)]
+mod forms {
+    #[derive(FromForm)]
+    pub struct PassBundleForm {
+        pub d: Vec<String>,
+    }
 
-            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-                formatter.write_str("struct EHealthHcert")
-            }
+    #[derive(FromForm)]
+    pub struct ShcChunkForm {
+        pub d: String,
+        pub session: Option<String>,
+    }
 
-            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
-                let mut eu_dgc_v1 = None;
-                while let Some(key) = map.next_key()? {
-                    match key {
-                        1 => {
-                            if eu_dgc_v1.is_some() {
-                                return Err(serde::de::Error::duplicate_field("eu_dgc_v1"));
-                            }
-                            eu_dgc_v1 = Some(map.next_value()?);
-                        }
-                        f => {
-                            return Err(serde::de::Error::unknown_field(&f.to_string(), &["1"]));
-                        }
-                    }
-                }
+    #[derive(FromForm)]
+    pub struct PassKitSerialsQuery {
+        #[field(name = "passesUpdatedSince")]
+        pub passes_updated_since: Option<String>,
+    }
+}
 
-                let eu_dgc_v1 = eu_dgc_v1.ok_or_else(|| serde::de::Error::missing_field("eu_dgc_v1"))?;
+#[post("/pkpasses", data = "<bundle>")]
+fn pkpasses(
+    bundle: rocket::form::Form<forms::PassBundleForm>,
+    trust_store: &rocket::State<TrustStore>,
+    pass_options: &rocket::State<PassOptions>,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<PKPassesResponse, ErrorResponse> {
+    if bundle.d.len() > MAX_BUNDLE_PASSES {
+        return Err(ErrorResponse(Error::InvalidPass("too many passes in bundle")));
+    }
 
-                Ok(EHealthHcert {
-                    eu_dgc_v1
-                })
-            }
+    let passes = bundle.into_inner().d.into_iter()
+        .map(|d| {
+            let (pass, update_metadata) = qr_data_to_pass(d, trust_store, **pass_options)?;
+            pass_issuer.sign_pkpass(pass, update_metadata)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ErrorResponse)?;
+    let bundle_bytes = pkpass::bundle_pkpasses(&passes).map_err(ErrorResponse)?;
+
+    Ok(PKPassesResponse(bundle_bytes))
+}
+
+#[post("/shc-chunk", data = "<chunk>")]
+fn shc_chunk(
+    chunk: rocket::form::Form<forms::ShcChunkForm>,
+    shc_assembler: &rocket::State<shc::ShcChunkAssembler>,
+) -> Result<rocket::serde::json::Json<shc::ShcChunkStatus>, ErrorResponse> {
+    let status = shc_assembler.add_chunk(chunk.session.as_deref(), &chunk.d).map_err(ErrorResponse)?;
+    Ok(rocket::serde::json::Json(status))
+}
+
+#[get("/shc-chunk/<session>")]
+fn shc_chunk_pass(
+    session: String,
+    platform: WalletPlatform,
+    shc_assembler: &rocket::State<shc::ShcChunkAssembler>,
+    trust_store: &rocket::State<TrustStore>,
+    pass_options: &rocket::State<PassOptions>,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<WalletResponse, ErrorResponse> {
+    let d = shc_assembler.take(&session).map_err(ErrorResponse)?;
+    let (pass, update_metadata) = qr_data_to_pass(d, trust_store, **pass_options).map_err(ErrorResponse)?;
+    pass_issuer.issue(pass, update_metadata, platform).map_err(ErrorResponse)
+}
+
+struct ApplePassAuthorization(String);
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for ApplePassAuthorization {
+    type Error = ();
+
+    async fn from_request(req: &'r rocket::request::Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        match req.headers().get_one("Authorization").and_then(|a| a.strip_prefix("ApplePass ")) {
+            Some(token) => rocket::request::Outcome::Success(ApplePassAuthorization(token.to_string())),
+            None => rocket::request::Outcome::Failure((rocket::http::Status::Unauthorized, ()))
         }
-
-        deserializer.deserialize_struct("", &[], Visitor)
     }
 }
 
-#[derive(Debug)]
-struct EHealthPayload {
-    iss: String,
-    iat: DateTime<Utc>,
-    exp: DateTime<Utc>,
-    hcert: EHealthHcert,
-}
-
-impl<'de> serde::Deserialize<'de> for EHealthPayload {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
-        struct Visitor;
+struct IfModifiedSince(Option<chrono::DateTime<chrono::FixedOffset>>);
 
-        impl<'de> serde::de::Visitor<'de> for Visitor {
-            type Value = EHealthPayload;
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for IfModifiedSince {
+    type Error = std::convert::Infallible;
 
-            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-                formatter.write_str("struct EHealthPayload")
-            }
+    async fn from_request(req: &'r rocket::request::Request<'_>) -> rocket::request::Outcome<Self, Self::Error> {
+        rocket::request::Outcome::Success(IfModifiedSince(
+            req.headers().get_one("If-Modified-Since").and_then(|d| chrono::DateTime::parse_from_rfc2822(d).ok())
+        ))
+    }
+}
 
-            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
-                let mut iss = None;
-                let mut iat = None;
-                let mut exp = None;
-                let mut hcert = None;
-                while let Some(key) = map.next_key()? {
-                    match key {
-                        1 => {
-                            if iss.is_some() {
-                                return Err(serde::de::Error::duplicate_field("iss"));
-                            }
-                            iss = Some(map.next_value()?);
-                        }
-                        6 => {
-                            if iat.is_some() {
-                                return Err(serde::de::Error::duplicate_field("iat"));
-                            }
-                            let iat_ts = map.next_value::<i64>()?;
-                            iat = Some(chrono::Utc.timestamp(iat_ts, 0));
-                        }
-                        4 => {
-                            if exp.is_some() {
-                                return Err(serde::de::Error::duplicate_field("exp"));
-                            }
-                            let exp_ts = map.next_value::<i64>()?;
-                            exp = Some(chrono::Utc.timestamp(exp_ts, 0));
-                        }
-                        -260 => {
-                            if hcert.is_some() {
-                                return Err(serde::de::Error::duplicate_field("hcert"));
-                            }
-                            hcert = Some(map.next_value()?);
-                        }
-                        f => {
-                            return Err(serde::de::Error::unknown_field(&f.to_string(), &["1", "6", "4", "-260"]));
-                        }
-                    }
-                }
+enum UpdatedPassResponse {
+    Pass(Vec<u8>, chrono::DateTime<chrono::Utc>),
+    NotModified,
+}
 
-                let iss = iss.ok_or_else(|| serde::de::Error::missing_field("iss"))?;
-                let iat = iat.ok_or_else(|| serde::de::Error::missing_field("iat"))?;
-                let exp = exp.ok_or_else(|| serde::de::Error::missing_field("exp"))?;
-                let hcert = hcert.ok_or_else(|| serde::de::Error::missing_field("hcert"))?;
-
-                Ok(EHealthPayload {
-                    iss,
-                    iat,
-                    exp,
-                    hcert,
-                })
-            }
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for UpdatedPassResponse {
+    fn respond_to(self, _req: &'r rocket::request::Request<'_>) -> rocket::response::Result<'o> {
+        match self {
+            UpdatedPassResponse::Pass(pass, last_modified) => rocket::Response::build()
+                .header(rocket::http::ContentType::new("application", "vnd.apple.pkpass"))
+                .raw_header("Last-Modified", last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .sized_body(pass.len(), std::io::Cursor::new(pass))
+                .ok(),
+            UpdatedPassResponse::NotModified => rocket::Response::build()
+                .status(rocket::http::Status::NotModified)
+                .ok(),
         }
-
-        deserializer.deserialize_struct("", &[], Visitor)
     }
 }
 
-#[get("/")]
-fn index() -> rocket_dyn_templates::Template {
-    rocket_dyn_templates::Template::render("index", std::collections::HashMap::<(), ()>::new())
+#[derive(Responder)]
+enum PassKitSerialsResponse {
+    Updated(rocket::serde::json::Json<PassKitSerialNumbers>),
+    NoneUpdated(rocket::http::Status),
 }
 
-#[get("/privacy")]
-fn privacy() -> rocket_dyn_templates::Template {
-    rocket_dyn_templates::Template::render("privacy", std::collections::HashMap::<(), ()>::new())
+#[derive(Debug, Serialize)]
+struct PassKitSerialNumbers {
+    #[serde(rename = "serialNumbers")]
+    serial_numbers: Vec<String>,
+    #[serde(rename = "lastUpdated")]
+    last_updated: String,
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorInfo {
-    error: &'static str,
+#[derive(Debug, Deserialize)]
+struct PassKitRegistration {
+    #[serde(rename = "pushToken")]
+    push_token: String,
 }
 
-#[get("/qr-data?<d>")]
-fn qr_data(
-    d: String,
-    signing_certs: &rocket::State<PassSigningCerts>,
-    pass_signing_keys: &rocket::State<PKPassSigningKeys>,
-) -> Result<PKPassResponse, rocket_dyn_templates::Template> {
-    let pkpass = if d.starts_with("HC1:") {
-        let hc_data_deflated = match base45::decode(&d[4..]) {
-            Ok(d) => d,
-            Err(e) => {
-                println!("Can't decode Base45: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid Base45"
-                }));
-            }
-        };
-        let mut deflater = flate2::read::ZlibDecoder::new(&hc_data_deflated[..]);
-        let mut hc_data = Vec::new();
-        match deflater.read_to_end(&mut hc_data) {
-            Ok(_) => {},
-            Err(e) => {
-                println!("Can't decode DEFLATE: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid DEFLATE encoding"
-                }));
-            }
-        };
+#[derive(Debug, Deserialize)]
+struct PassKitLogs {
+    logs: Vec<String>,
+}
 
-        let cose_data = match coset::CoseSign1::from_tagged_slice(&hc_data) {
-            Ok(d) => d,
-            Err(e) => {
-                println!("Can't decode COSE: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid COSE data"
-                }));
-            }
-        };
+fn pass_updates(pass_issuer: &PassIssuer) -> Result<&passupdates::PassUpdateService, rocket::http::Status> {
+    pass_issuer.pass_updates.as_ref().ok_or(rocket::http::Status::NotFound)
+}
 
-        let payload_bytes = match &cose_data.payload {
-            Some(d) => d,
-            None => {
-                println!("No COSE payload");
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "No payload in COSE data"
-                }));
-            }
-        };
+fn passkit_error(e: Error) -> rocket::http::Status {
+    error!("PassKit web service error: {}", e);
+    rocket::http::Status::InternalServerError
+}
 
-        let payload = match serde_cbor::from_slice::<EHealthPayload>(&payload_bytes) {
-            Ok(d) => d,
-            Err(e) => {
-                println!("Can't decode payload: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid payload"
-                }));
-            }
-        };
+fn check_passkit_authorisation(
+    pass_updates: &passupdates::PassUpdateService, pass_type_id: &str, serial: &str, auth: &ApplePassAuthorization,
+) -> Result<(), rocket::http::Status> {
+    match pass_updates.is_authorised(pass_type_id, serial, &auth.0).map_err(passkit_error)? {
+        true => Ok(()),
+        false => Err(rocket::http::Status::Unauthorized)
+    }
+}
 
-        if VERIFIABLE_COUNTRIES.contains(&payload.iss.as_str()) {
-            let cert_key = PassSigningCertKey {
-                iss: payload.iss.clone(),
-                kid: cose_data.protected.key_id.clone(),
-            };
-
-            let signing_cert = match signing_certs.0.get(&cert_key) {
-                Some(d) => d,
-                None => {
-                    println!("No known signing key");
-                    return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                        error: "Signed by an unknown key"
-                    }));
-                }
-            };
-
-            match cose_data.verify_signature(&[], |sig, data| {
-                match cose_data.protected.alg.clone().unwrap_or_default() {
-                    coset::Algorithm::Assigned(coset::iana::Algorithm::ES256) => {
-                        if sig.len() != 64 {
-                            return Err("invalid signature length".to_string());
-                        }
-                        let r = openssl::bn::BigNum::from_slice(&sig[0..32]).map_err(|e| e.to_string())?;
-                        let s = openssl::bn::BigNum::from_slice(&sig[32..64]).map_err(|e| e.to_string())?;
-                        let sig = openssl::ecdsa::EcdsaSig::from_private_components(r, s).map_err(|e| e.to_string())?;
-                        let hash = openssl::hash::hash(
-                            openssl::hash::MessageDigest::sha256(), data,
-                        ).map_err(|e| e.to_string())?;
-                        if sig.verify(
-                            hash.as_ref(),
-                            signing_cert.pkey.ec_key().map_err(|e| e.to_string())?.as_ref(),
-                        ).map_err(|e| e.to_string())? {
-                            Ok(())
-                        } else {
-                            Err("signature failed to verify".to_string())
-                        }
-                    }
-                    coset::Algorithm::Assigned(coset::iana::Algorithm::PS256) => {
-                        let mut verifier = openssl::sign::Verifier::new(
-                            openssl::hash::MessageDigest::sha256(), &signing_cert.pkey,
-                        ).map_err(|e| e.to_string())?;
-                        verifier.update(data).map_err(|e| e.to_string())?;
-                        if verifier.verify(sig).map_err(|e| e.to_string())? {
-                            Ok(())
-                        } else {
-                            Err("signature failed to verify".to_string())
-                        }
-                    }
-                    a => {
-                        return Err(format!("Unsupported signing alg: {:?}", a));
-                    }
-                }
-            }) {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("Signature verification failed: {}", e);
-                    return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                        error: "Invalid signature"
-                    }));
-                }
+#[post("/v1/devices/<device_id>/registrations/<pass_type_id>/<serial>", data = "<registration>")]
+fn passkit_register(
+    device_id: String,
+    pass_type_id: String,
+    serial: String,
+    auth: ApplePassAuthorization,
+    registration: rocket::serde::json::Json<PassKitRegistration>,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<rocket::http::Status, rocket::http::Status> {
+    let pass_updates = pass_updates(pass_issuer)?;
+    check_passkit_authorisation(pass_updates, &pass_type_id, &serial, &auth)?;
+    let created = pass_updates.register_device(&device_id, &pass_type_id, &serial, &registration.push_token)
+        .map_err(passkit_error)?;
+    Ok(if created { rocket::http::Status::Created } else { rocket::http::Status::Ok })
+}
+
+#[get("/v1/devices/<device_id>/registrations/<pass_type_id>?<query..>")]
+fn passkit_serials(
+    device_id: String,
+    pass_type_id: String,
+    query: forms::PassKitSerialsQuery,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<PassKitSerialsResponse, rocket::http::Status> {
+    let pass_updates = pass_updates(pass_issuer)?;
+    let since = query.passes_updated_since.and_then(|s| s.parse::<i64>().ok());
+    Ok(match pass_updates.updated_serials(&device_id, &pass_type_id, since).map_err(passkit_error)? {
+        Some((serial_numbers, last_updated)) => PassKitSerialsResponse::Updated(rocket::serde::json::Json(
+            PassKitSerialNumbers {
+                serial_numbers,
+                last_updated: last_updated.to_string(),
             }
-        }
+        )),
+        None => PassKitSerialsResponse::NoneUpdated(rocket::http::Status::NoContent)
+    })
+}
 
-        match ehealth_payload_to_pkpass(payload, d) {
-            Ok(p) => p,
-            Err(e) => {
-                println!("Unable to create pkpass: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid pass"
-                }));
-            }
-        }
-    } else if d.starts_with("https://covidasidogrulama.saglik.gov.tr/api/CovidAsiKartiDogrula") {
-        match turkey_payload_to_pkpass(d) {
-            Ok(p) => p,
-            Err(e) => {
-                println!("Unable to create pkpass: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid pass"
-                }));
-            }
-        }
-    } else if TR_HES_REGEX.is_match(&d) {
-        match turkey_hes_payload_to_pkpass(d) {
-            Ok(p) => p,
-            Err(e) => {
-                println!("Unable to create pkpass: {}", e);
-                return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                    error: "Invalid pass"
-                }));
-            }
-        }
-    } else {
-        return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-            error: "Not an eHealth QR code"
-        }));
+#[delete("/v1/devices/<device_id>/registrations/<pass_type_id>/<serial>")]
+fn passkit_unregister(
+    device_id: String,
+    pass_type_id: String,
+    serial: String,
+    auth: ApplePassAuthorization,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<rocket::http::Status, rocket::http::Status> {
+    let pass_updates = pass_updates(pass_issuer)?;
+    check_passkit_authorisation(pass_updates, &pass_type_id, &serial, &auth)?;
+    pass_updates.unregister_device(&device_id, &pass_type_id, &serial).map_err(passkit_error)?;
+    Ok(rocket::http::Status::Ok)
+}
+
+#[get("/v1/passes/<pass_type_id>/<serial>")]
+fn passkit_pass(
+    pass_type_id: String,
+    serial: String,
+    auth: ApplePassAuthorization,
+    if_modified_since: IfModifiedSince,
+    pass_issuer: &rocket::State<PassIssuer>,
+) -> Result<UpdatedPassResponse, rocket::http::Status> {
+    let pass_updates = pass_updates(pass_issuer)?;
+    check_passkit_authorisation(pass_updates, &pass_type_id, &serial, &auth)?;
+    let stored = pass_updates.pass(&pass_type_id, &serial).map_err(passkit_error)?
+        .ok_or(rocket::http::Status::NotFound)?;
+
+    let status = match stored.update_status(if_modified_since.0) {
+        Some(status) => status,
+        None => return Ok(UpdatedPassResponse::NotModified)
     };
 
-    let pkpass_bytes = match sign_pkpass(&pkpass, &pass_signing_keys) {
-        Ok(d) => d,
-        Err(e) => {
-            println!("Can't encode pass: {}", e);
-            return Err(rocket_dyn_templates::Template::render("error", ErrorInfo {
-                error: "Unable to generate pass"
-            }));
-        }
-    };
+    let mut pass = pkpass::voided_pkpass(serial, stored.description, stored.org_name, status);
+    pass.web_service_url = Some(pass_updates.web_service_url().to_string());
+    pass.authentication_token = Some(auth.0);
+    let pass_bytes = pkpass::sign_pkpass(&pass, &pass_issuer.signing_keys).map_err(passkit_error)?;
+    Ok(UpdatedPassResponse::Pass(pass_bytes, stored.updated_at))
+}
 
-    Ok(PKPassResponse(pkpass_bytes))
+#[post("/v1/log", data = "<logs>")]
+fn passkit_log(logs: rocket::serde::json::Json<PassKitLogs>) -> rocket::http::Status {
+    for log in &logs.logs {
+        info!("PassKit log: {}", log);
+    }
+    rocket::http::Status::Ok
 }
 
+
 #[rocket::launch]
-fn rocket() -> _ {
-    // let uk_certs: Vec<UKSigningCert> = reqwest::blocking::get(UK_CERT_URL)
-    //     .expect("Unable to download UK signing certs")
-    //     .json()
-    //     .expect("Unable to decode UK signing certs");
-
-    let mut signing_certs = PassSigningCerts(std::collections::HashMap::new());
-
-    // for cert in uk_certs {
-    //     signing_certs.0.insert(PassSigningCertKey {
-    //         iss: "GB".to_string(),
-    //         kid: cert.kid,
-    //     }, PassSigningCert {
-    //         pkey: openssl::pkey::PKey::from_ec_key(cert.public_key).unwrap()
-    //     });
-    // }
+async fn rocket() -> _ {
+    // Building first sets up Rocket's logger, so value set and trust list loading is logged
+    let rocket = rocket::build();
+
+    let trust_config: TrustConfig = rocket::Config::figment().extract()
+        .expect("Invalid trust list configuration");
+    let decode_config: DecodeConfig = rocket::Config::figment().extract()
+        .expect("Invalid decode configuration");
+    let google_wallet_config: GoogleWalletConfig = rocket::Config::figment().extract()
+        .expect("Invalid Google Wallet configuration");
+    let pass_update_config: PassUpdateConfig = rocket::Config::figment().extract()
+        .expect("Invalid pass update configuration");
+
+    if let Some(value_set_dir) = &decode_config.value_set_dir {
+        valuesets::install(
+            valuesets::ValueSets::load_from_dir(value_set_dir).await.expect("Unable to load value sets")
+        );
+    }
 
     let mut intermediate_certs = openssl::stack::Stack::new().unwrap();
 
@@ -1267,12 +659,199 @@ fn rocket() -> _ {
         intermediate_certs,
     };
 
-    rocket::build()
+    let google_wallet_key = match (
+        &google_wallet_config.google_wallet_issuer_id, &google_wallet_config.google_wallet_service_account
+    ) {
+        (Some(issuer_id), Some(service_account)) => Some(google_wallet::GoogleWalletSigningKey::from_service_account_json(
+            issuer_id, &google_wallet_config.google_wallet_class,
+            &std::fs::read(service_account).expect("Unable to read Google Wallet service account key"),
+        ).expect("Invalid Google Wallet service account key")),
+        _ => None
+    };
+
+    let pass_updates = match (
+        &pass_update_config.pass_update_db, &pass_update_config.pass_update_db_key,
+        &pass_update_config.pass_web_service_url,
+    ) {
+        (Some(db), Some(db_key), Some(web_service_url)) => Some(passupdates::PassUpdateService::open(
+            db, db_key, web_service_url,
+            passupdates::ApnsClient::new(&signing_keys).expect("Unable to set up pass update pushes"),
+        ).expect("Unable to open pass update store")),
+        _ => None
+    };
+
+    let trust_store = TrustStore::new(trust_config.sources()).await
+        .expect("Unable to load trust lists");
+
+    if let Some(pass_updates) = &pass_updates {
+        void_revoked(pass_updates.clone(), trust_store.current()).await;
+    }
+
+    if let Some(refresh_interval) = trust_config.trust_list_refresh_interval.filter(|i| *i > 0) {
+        trust_store.spawn_refresh(std::time::Duration::from_secs(refresh_interval), pass_updates.clone());
+    }
+
+    let shc_assembler = shc::ShcChunkAssembler::new(std::time::Duration::from_secs(SHC_SESSION_TTL));
+    spawn_shc_session_expiry(shc_assembler.clone());
+
+    rocket
         .attach(rocket_dyn_templates::Template::fairing())
-        .manage(signing_certs)
-        .manage(signing_keys)
+        .manage(trust_store)
+        .manage(PassIssuer {
+            signing_keys,
+            google_wallet_key,
+            pass_updates,
+        })
+        .manage(shc_assembler)
+        .manage(PassOptions {
+            decode_mode: decode_config.decode_mode,
+            validity_policy: decode_config.validity_policy,
+        })
         .mount("/static", rocket::fs::FileServer::from("./static"))
         .mount("/", routes![
-            index, qr_data, privacy
+            index, qr_data, pkpasses, shc_chunk, shc_chunk_pass, privacy, value_sets
         ])
-}
\ No newline at end of file
+        .mount("/passkit", routes![
+            passkit_register, passkit_serials, passkit_unregister, passkit_pass, passkit_log
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+
+    /// EU DCCs for the second dose of a vaccination, and a re-issue of it, signed with a throwaway key
+    const HC1: &str = "HC1:6BFOXNSTSMAHN-HQA7HM6QA8JO4 G2EP4J0B3KL+MP+3B5B9$WD.NK-MPW$NLEEMJC7ZS2%KYZP-3WK34JWLG56H0API0Z.2W+F245EL4.J2V19ERAVAER6A0SKA18H15BDK38CUAWGL6A2F9%C2%K:XF:EF.A5:S9395*CBVZ0K1H$$0VON/:IZJJ.V47NV/HLWU4L8S1JPTQNXHF+X47Y45W4MKN4NN3F85QNCY0O%0$88:0LPHN6D7LLK*2HG%89UV-0LZ 2S-O:S9UZ4+FJE 4Y3LL/II 0OC9JU0D0HT0HB2PR78+FFZI9$JAQJKKIJX2MM+GWHKSKE MCAOI8%MCU5VTQDPIRPK/PKESQC.UTWA6QK.-T3-SY$NCU5CIQ 52564L64GIQC.UKMI$8R+1A7CPFRMLNKNM8POCJPG.-0D13:*KE8W**T%%FK9UTRGW7IY4O8DD8DAI 53$D3UIG/HS*PEJJS9D4JI4CVBAN7QS6UM5WVMVHJ7O:*82:99UTRMV+MTONSCUDH00*OAQ1";
+    const REISSUED_HC1: &str = "HC1:6BFOXNSTSMAHN-HQA7HM66E8MBW3XHP+56R5PD9:5IO/CZK99U62HRQHIZC4.OI1RM8ZA*LPOW2GHKW/F3IKJ5QH*AF/GJ5MT0K*/GB2PN2LUSVH3MBMOZJK8-5./G0OPN48IMI4UUIMI.J9WVHWVH+ZE/T95%HHRIJRH.OG4SIIRH/R2UZUWM6J$7XLH5G6TH95NITK292W7*RBT1ON1EYH$NL SI5K1*TB3:U-1VVS1UU15%HTNIPPAAMI PQVW5/O16%HAT1Z%PHOP+MMBT16Y5+Z9XV7G+SB.V Q5 M97K2EH0PZBGWK*%NH$RSC9FFFP4OO-O/HL*DD2IHLF95HFI1MAKJ%IH1FDLW4L4OVIOE1MA.DI1ITNP8EFDKLDD846SWKP/HLIJLKNF8JF172X0CM278ORGYU.PA.WD.%L6$0N$IFURM:PEU4KJ4C.Q3AF44CN7Q0OLM%P4+FI*E*NFDFWVE4UILED2P14XIRQO5UA6$Y6-%5-JE000W%MTFE";
+
+    fn pass_signing_keys() -> PKPassSigningKeys {
+        let private_key = openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", pkpass::PASS_TYPE_ID).unwrap();
+        let name = name.build();
+        let mut cert = openssl::x509::X509Builder::new().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&private_key).unwrap();
+        cert.set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap()).unwrap();
+        cert.set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap()).unwrap();
+        cert.sign(&private_key, openssl::hash::MessageDigest::sha256()).unwrap();
+
+        PKPassSigningKeys {
+            public_cert: cert.build(),
+            private_key,
+            intermediate_certs: openssl::stack::Stack::new().unwrap(),
+        }
+    }
+
+    fn register(pass_updates: &passupdates::PassUpdateService, data: &str) -> pkpass::PKPass {
+        let hc1 = decode::decode_hc1(data, decode::DecodeMode::Strict).unwrap();
+        let metadata = passupdates::PassUpdateMetadata::for_hc1(&hc1, verify::Verification::Verified).unwrap().unwrap();
+        let mut pass = pkpass::ehealth_payload_to_pkpass(hc1.payload, data.to_string()).unwrap();
+        pass_updates.register_pass(&mut pass, metadata).unwrap();
+        pass
+    }
+
+    fn client(pass_updates: passupdates::PassUpdateService) -> Client {
+        let rocket = rocket::build()
+            .manage(PassIssuer {
+                signing_keys: pass_signing_keys(),
+                google_wallet_key: None,
+                pass_updates: Some(pass_updates),
+            })
+            .manage(TrustStore {
+                sources: std::sync::Arc::new(vec![]),
+                signing_certs: std::sync::Arc::new(std::sync::RwLock::new(std::sync::Arc::new(PassSigningCerts::default()))),
+            })
+            .manage(PassOptions {
+                decode_mode: decode::DecodeMode::Strict,
+                validity_policy: ValidityPolicy::Ignore,
+            })
+            .attach(rocket_dyn_templates::Template::fairing())
+            .mount("/", routes![pkpasses])
+            .mount("/passkit", routes![
+                passkit_register, passkit_serials, passkit_unregister, passkit_pass, passkit_log
+            ]);
+        Client::tracked(rocket).unwrap()
+    }
+
+    fn pass_updates() -> passupdates::PassUpdateService {
+        let apns = passupdates::ApnsClient::new(&pass_signing_keys()).unwrap();
+        passupdates::PassUpdateService::open(":memory:", "passphrase", "https://example.com/passkit", apns).unwrap()
+    }
+
+    fn authorization(pass: &pkpass::PKPass) -> Header<'static> {
+        Header::new("Authorization", format!("ApplePass {}", pass.authentication_token.as_deref().unwrap()))
+    }
+
+    #[test]
+    fn passkit_web_service() {
+        let pass_updates = pass_updates();
+        let pass = register(&pass_updates, HC1);
+        let client = client(pass_updates.clone());
+        let registration = format!("/passkit/v1/devices/device/registrations/{}/{}", pass.type_id, pass.serial);
+        let pass_url = format!("/passkit/v1/passes/{}/{}", pass.type_id, pass.serial);
+        let serials_url = format!("/passkit/v1/devices/device/registrations/{}", pass.type_id);
+
+        let register_device = |auth: Header<'static>| client.post(&registration)
+            .header(auth)
+            .header(ContentType::JSON)
+            .body(r#"{"pushToken": "push-token"}"#)
+            .dispatch().status();
+        assert_eq!(register_device(Header::new("Authorization", "ApplePass wrong")), Status::Unauthorized);
+        assert_eq!(register_device(authorization(&pass)), Status::Created);
+        assert_eq!(register_device(authorization(&pass)), Status::Ok);
+
+        assert_eq!(client.get(&pass_url).header(authorization(&pass)).dispatch().status(), Status::NotModified);
+        let serials = client.get(&serials_url).dispatch().into_json::<serde_json::Value>().unwrap();
+        assert_eq!(serials["serialNumbers"], serde_json::json!([pass.serial]));
+        let updated_since = format!("{}?passesUpdatedSince={}", serials_url, serials["lastUpdated"].as_str().unwrap());
+        assert_eq!(client.get(&updated_since).dispatch().status(), Status::NoContent);
+
+        // Update tags have millisecond precision
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let reissued = register(&pass_updates, REISSUED_HC1);
+        assert_ne!(reissued.serial, pass.serial);
+        let serials = client.get(&updated_since).dispatch().into_json::<serde_json::Value>().unwrap();
+        assert_eq!(serials["serialNumbers"], serde_json::json!([pass.serial]));
+
+        let updated = client.get(&pass_url).header(authorization(&pass)).dispatch();
+        assert_eq!(updated.status(), Status::Ok);
+        assert_eq!(updated.content_type(), Some(ContentType::new("application", "vnd.apple.pkpass")));
+        let last_modified = updated.headers().get_one("Last-Modified").unwrap().to_string();
+        assert!(!updated.into_bytes().unwrap().is_empty());
+        let not_modified = client.get(&pass_url)
+            .header(authorization(&pass))
+            .header(Header::new("If-Modified-Since", last_modified))
+            .dispatch();
+        assert_eq!(not_modified.status(), Status::NotModified);
+
+        assert_eq!(client.delete(&registration).header(authorization(&pass)).dispatch().status(), Status::Ok);
+        assert_eq!(client.get(&serials_url).dispatch().status(), Status::NoContent);
+    }
+
+    #[test]
+    fn pkpasses_bundle() {
+        let client = client(pass_updates());
+        let form = |count: usize| {
+            let d = HC1.bytes().map(|b| format!("%{:02X}", b)).collect::<String>();
+            vec![format!("d={}", d); count].join("&")
+        };
+        let post_bundle = |count: usize| client.post("/pkpasses")
+            .header(ContentType::Form)
+            .body(form(count))
+            .dispatch();
+
+        let bundle = post_bundle(MAX_BUNDLE_PASSES);
+        assert_eq!(bundle.status(), Status::Ok);
+        assert_eq!(bundle.content_type(), Some(ContentType::new("application", "vnd.apple.pkpasses")));
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bundle.into_bytes().unwrap())).unwrap();
+        assert_eq!(archive.len(), MAX_BUNDLE_PASSES);
+
+        assert_eq!(post_bundle(MAX_BUNDLE_PASSES + 1).status(), Status::BadRequest);
+        assert_eq!(post_bundle(0).status(), Status::BadRequest);
+    }
+}
@@ -0,0 +1,248 @@
+use chrono::prelude::*;
+use coset::TaggedCborSerializable;
+use serde::Deserializer;
+use std::fmt::Formatter;
+use crate::error::Error;
+
+#[derive(Debug)]
+pub struct NZCPCertificate {
+    pub cose: coset::CoseSign1,
+    /// The protected header as signed, `coset` re-encodes it in its own key order
+    pub protected_header: Vec<u8>,
+    pub payload: NZCPPayload,
+}
+
+#[derive(Debug)]
+pub struct NZCPPayload {
+    pub iss: String,
+    pub nbf: DateTime<Utc>,
+    pub exp: DateTime<Utc>,
+    pub cti: Vec<u8>,
+    pub vc: NZCPCredential,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NZCPCredential {
+    #[serde(rename = "@context")]
+    pub context: Vec<String>,
+    pub version: String,
+    #[serde(rename = "type")]
+    pub types: Vec<String>,
+    #[serde(rename = "credentialSubject")]
+    pub subject: NZCPCredentialSubject,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NZCPCredentialSubject {
+    #[serde(rename = "givenName")]
+    pub given_name: String,
+    #[serde(rename = "familyName", default)]
+    pub family_name: Option<String>,
+    #[serde(deserialize_with = "de_date")]
+    pub dob: NaiveDate,
+}
+
+impl NZCPCredentialSubject {
+    pub fn display_name(&self) -> String {
+        match &self.family_name {
+            Some(family_name) => format!("{} {}", self.given_name, family_name),
+            None => self.given_name.clone()
+        }
+    }
+}
+
+fn de_date<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDate, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).and_then(|s| NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(serde::de::Error::custom))
+}
+
+impl<'de> serde::Deserialize<'de> for NZCPPayload {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = NZCPPayload;
+
+            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+                formatter.write_str("struct NZCPPayload")
+            }
+
+            fn visit_map<V: serde::de::MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
+                let mut iss = None;
+                let mut nbf = None;
+                let mut exp = None;
+                let mut cti = None;
+                let mut vc = None;
+                while let Some(key) = map.next_key::<serde_cbor::Value>()? {
+                    match key {
+                        serde_cbor::Value::Integer(1) => {
+                            if iss.is_some() {
+                                return Err(serde::de::Error::duplicate_field("iss"));
+                            }
+                            iss = Some(map.next_value()?);
+                        }
+                        serde_cbor::Value::Integer(5) => {
+                            if nbf.is_some() {
+                                return Err(serde::de::Error::duplicate_field("nbf"));
+                            }
+                            let nbf_ts = map.next_value::<i64>()?;
+                            nbf = Some(chrono::Utc.timestamp_opt(nbf_ts, 0).single()
+                                .ok_or_else(|| serde::de::Error::custom("nbf is out of range"))?);
+                        }
+                        serde_cbor::Value::Integer(4) => {
+                            if exp.is_some() {
+                                return Err(serde::de::Error::duplicate_field("exp"));
+                            }
+                            let exp_ts = map.next_value::<i64>()?;
+                            exp = Some(chrono::Utc.timestamp_opt(exp_ts, 0).single()
+                                .ok_or_else(|| serde::de::Error::custom("exp is out of range"))?);
+                        }
+                        serde_cbor::Value::Integer(7) => {
+                            if cti.is_some() {
+                                return Err(serde::de::Error::duplicate_field("cti"));
+                            }
+                            cti = match map.next_value()? {
+                                serde_cbor::Value::Bytes(b) => Some(b),
+                                _ => return Err(serde::de::Error::custom("cti must be a byte string"))
+                            };
+                        }
+                        serde_cbor::Value::Text(k) if k == "vc" => {
+                            if vc.is_some() {
+                                return Err(serde::de::Error::duplicate_field("vc"));
+                            }
+                            vc = Some(map.next_value()?);
+                        }
+                        _ => {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let iss = iss.ok_or_else(|| serde::de::Error::missing_field("iss"))?;
+                let nbf = nbf.ok_or_else(|| serde::de::Error::missing_field("nbf"))?;
+                let exp = exp.ok_or_else(|| serde::de::Error::missing_field("exp"))?;
+                let cti = cti.ok_or_else(|| serde::de::Error::missing_field("cti"))?;
+                let vc = vc.ok_or_else(|| serde::de::Error::missing_field("vc"))?;
+
+                Ok(NZCPPayload {
+                    iss,
+                    nbf,
+                    exp,
+                    cti,
+                    vc,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+pub fn decode_nzcp(data: &str) -> Result<NZCPCertificate, Error> {
+    let nzcp_data_b32 = data.strip_prefix("NZCP:/").ok_or(Error::Unsupported)?;
+    let nzcp_data_b32 = nzcp_data_b32.strip_prefix("1/")
+        .ok_or(Error::Nzcp("Unsupported NZ COVID Pass version"))?;
+    let nzcp_data = base32::decode(base32::Alphabet::RFC4648 { padding: false }, nzcp_data_b32)
+        .ok_or(Error::Base32)?;
+
+    let cose = coset::CoseSign1::from_tagged_slice(&nzcp_data)
+        .map_err(Error::Cose)?;
+    let protected_header = match serde_cbor::from_slice(&nzcp_data).map_err(Error::Cbor)? {
+        serde_cbor::Value::Tag(_, cose) => match *cose {
+            serde_cbor::Value::Array(mut parts) if !parts.is_empty() => match parts.swap_remove(0) {
+                serde_cbor::Value::Bytes(b) => b,
+                _ => return Err(Error::Nzcp("Invalid COSE protected header"))
+            },
+            _ => return Err(Error::Nzcp("Invalid COSE structure"))
+        },
+        _ => return Err(Error::Nzcp("Invalid COSE structure"))
+    };
+
+    let payload_bytes = cose.payload.as_ref().ok_or(Error::NoPayload)?;
+    let payload = serde_cbor::from_slice::<NZCPPayload>(payload_bytes)
+        .map_err(Error::from_payload_error)?;
+
+    if payload.cti.len() != 16 {
+        return Err(Error::Nzcp("Invalid credential identifier"));
+    }
+    if !payload.vc.types.iter().any(|t| t == "VerifiableCredential") ||
+        !payload.vc.types.iter().any(|t| t == "PublicCovidPass") {
+        return Err(Error::Nzcp("Not a NZ public COVID pass"));
+    }
+    if !payload.vc.version.starts_with("1.") {
+        return Err(Error::Nzcp("Unsupported NZ COVID Pass credential version"));
+    }
+
+    Ok(NZCPCertificate {
+        cose,
+        protected_header,
+        payload,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The valid worked example from the NZ COVID Pass specification.
+    const EXAMPLE_PASS: &str = "NZCP:/1/2KCEVIQEIVVWK6JNGEASNICZAEP2KALYDZSGSZB2O5SWEOTOPJRXALTDN53GSZBRHEXGQZLBNR2GQLTOPICRUYMBTIFAIGTUKBAAUYTWMOSGQQDDN5XHIZLYOSBHQJTIOR2HA4Z2F4XXO53XFZ3TGLTPOJTS6MRQGE4C6Y3SMVSGK3TUNFQWY4ZPOYYXQKTIOR2HA4Z2F4XW46TDOAXGG33WNFSDCOJONBSWC3DUNAXG46RPMNXW45DFPB2HGL3WGFTXMZLSONUW63TFGEXDALRQMR2HS4DFQJ2FMZLSNFTGSYLCNRSUG4TFMRSW45DJMFWG6UDVMJWGSY2DN53GSZCQMFZXG4LDOJSWIZLOORUWC3CTOVRGUZLDOSRWSZ3JOZSW4TTBNVSWISTBMNVWUZTBNVUWY6KOMFWWKZ2TOBQXE4TPO5RWI33CNIYTSNRQFUYDILJRGYDVAYFE6VGU4MCDGK7DHLLYWHVPUS2YIDJOA6Y524TD3AZRM263WTY2BE4DPKIF27WKF3UDNNVSVWRDYIYVJ65IRJJJ6Z25M2DO4YZLBHWFQGVQR5ZLIWEQJOZTS3IQ7JTNCFDX";
+
+    /// The example issuer's DID document from the NZ COVID Pass specification.
+    const EXAMPLE_DID_DOCUMENT: &str = r#"{
+        "@context": "https://w3.org/ns/did/v1",
+        "id": "did:web:nzcp.covid19.health.nz",
+        "verificationMethod": [{
+            "id": "did:web:nzcp.covid19.health.nz#key-1",
+            "controller": "did:web:nzcp.covid19.health.nz",
+            "type": "JsonWebKey2020",
+            "publicKeyJwk": {
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "zRR-XGsCp12Vvbgui4DD6O6cqmhfPuXMhi1OxPl8760",
+                "y": "Iv5SU6FuW-TRYh5_GOrJlcV_gpF_GpFQhCOD8LSk3T0"
+            }
+        }],
+        "assertionMethod": ["did:web:nzcp.covid19.health.nz#key-1"]
+    }"#;
+
+    #[test]
+    fn decodes_example_pass() {
+        let pass = decode_nzcp(EXAMPLE_PASS).unwrap();
+        assert_eq!(pass.payload.iss, "did:web:nzcp.covid19.health.nz");
+        assert_eq!(pass.payload.vc.subject.display_name(), "Jack Sparrow");
+        assert_eq!(pass.payload.vc.subject.dob, NaiveDate::from_ymd_opt(1960, 4, 16).unwrap());
+        assert_eq!(pass.payload.nbf.timestamp(), 1635883530);
+        assert_eq!(pass.payload.exp.timestamp(), 1951416330);
+    }
+
+    #[test]
+    fn rejects_out_of_range_dates() {
+        let payload = serde_cbor::Value::Map(vec![
+            (serde_cbor::Value::Integer(1), serde_cbor::Value::Text("did:web:nzcp.covid19.health.nz".to_string())),
+            (serde_cbor::Value::Integer(5), serde_cbor::Value::Integer(i64::MAX as i128)),
+        ].into_iter().collect());
+        let bytes = serde_cbor::to_vec(&payload).unwrap();
+        let err = serde_cbor::from_slice::<NZCPPayload>(&bytes).unwrap_err();
+        assert!(err.to_string().contains("nbf is out of range"));
+    }
+
+    #[test]
+    fn rejects_untrusted_issuer() {
+        let pass = decode_nzcp(EXAMPLE_PASS).unwrap();
+        let trust = crate::verify::PassSigningCerts::default();
+        let err = trust.verify_nzcp(&pass).unwrap_err();
+        assert!(matches!(err, Error::UnknownSigningKey));
+        assert_eq!((err.kind(), err.http_status()), ("signature", 422));
+    }
+
+    #[rocket::async_test]
+    async fn verifies_example_pass_against_example_did_document() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("nzcp.covid19.health.nz.json"), EXAMPLE_DID_DOCUMENT).unwrap();
+        let trust = crate::verify::TrustListSource::DIDDocumentDir(dir.path().to_str().unwrap().to_string())
+            .load().await.unwrap();
+
+        let pass = decode_nzcp(EXAMPLE_PASS).unwrap();
+        assert_eq!(trust.verify_nzcp(&pass).unwrap(), crate::verify::Verification::Verified);
+    }
+}
@@ -0,0 +1,414 @@
+use chrono::prelude::*;
+use std::collections::HashMap;
+use crate::dcc::{DateOrDateTime, EHealthPayload, EUDigitalGreenCertGroup, strip_uvci};
+use crate::error::Error;
+use crate::nzcp::NZCPCertificate;
+use crate::shc::SmartHealthCard;
+
+/// The contents of a pass independent of the wallet it is added to, rendered to a `PKPass` by
+/// `pkpass::pass_to_pkpass` and to a Google Wallet object by `google_wallet::pass_to_generic_object`.
+#[derive(Debug, Clone)]
+pub struct Pass {
+    pub serial: String,
+    pub description: String,
+    pub org_name: String,
+    pub logo_text: String,
+    pub colours: PassColours,
+    pub header_fields: Vec<PassField>,
+    pub primary_fields: Vec<PassField>,
+    pub secondary_fields: Vec<PassField>,
+    pub aux_fields: Vec<PassField>,
+    pub back_fields: Vec<PassField>,
+    /// Message of the QR code shown on the pass
+    pub barcode: String,
+    pub exp_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+#[derive(Debug, Clone, Copy)]
+pub struct PassColours {
+    pub background: Rgb,
+    pub foreground: Rgb,
+    pub label: Rgb,
+}
+
+#[derive(Debug, Clone)]
+pub struct PassField {
+    pub key: String,
+    /// English label, translated by `localisation::translate_label`
+    pub label: String,
+    pub value: PassFieldValue,
+    /// Translations of a text value by language code
+    pub value_translations: HashMap<String, String>,
+    /// Whether the value is a link the wallet should make tappable
+    pub link: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassFieldValue {
+    Text(String),
+    /// A calendar date, shown the same in every time zone
+    Date(NaiveDate),
+    /// A point in time, shown as its date only
+    DateOf(DateTime<FixedOffset>),
+    /// A point in time, shown with its date and time
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl PassField {
+    pub fn text<V: Into<String>>(key: &str, label: &str, value: V) -> Self {
+        PassField::new(key, label, PassFieldValue::Text(value.into()))
+    }
+
+    pub fn link<V: Into<String>>(key: &str, label: &str, value: V) -> Self {
+        PassField {
+            link: true,
+            ..PassField::text(key, label, value)
+        }
+    }
+
+    pub fn date(key: &str, label: &str, value: NaiveDate) -> Self {
+        PassField::new(key, label, PassFieldValue::Date(value))
+    }
+
+    pub fn date_of<Tz: TimeZone>(key: &str, label: &str, value: &DateTime<Tz>) -> Self {
+        PassField::new(key, label, PassFieldValue::DateOf(value.with_timezone(&value.offset().fix())))
+    }
+
+    pub fn date_time<Tz: TimeZone>(key: &str, label: &str, value: &DateTime<Tz>) -> Self {
+        PassField::new(key, label, PassFieldValue::DateTime(value.with_timezone(&value.offset().fix())))
+    }
+
+    fn date_or_date_time(key: &str, label: &str, value: &DateOrDateTime) -> Self {
+        match value {
+            DateOrDateTime::Date(d) => PassField::date(key, label, *d),
+            DateOrDateTime::DateTime(d) => PassField::date_time(key, label, d),
+        }
+    }
+
+    fn new(key: &str, label: &str, value: PassFieldValue) -> Self {
+        PassField {
+            key: key.to_string(),
+            label: label.to_string(),
+            value,
+            value_translations: HashMap::new(),
+            link: false,
+        }
+    }
+
+    pub fn translated(self, value_translations: HashMap<String, String>) -> Self {
+        PassField {
+            value_translations,
+            ..self
+        }
+    }
+}
+
+impl Pass {
+    pub fn add_warning(&mut self, warning: &str) {
+        self.header_fields.insert(0, PassField::text("warning", "Status", warning));
+    }
+}
+
+pub fn ehealth_payload_to_pass(payload: EHealthPayload, msg: String) -> Result<Pass, Error> {
+    let (serial, disease, issued_by) = match &payload.hcert.eu_dgc_v1.group {
+        EUDigitalGreenCertGroup::Vaccination(v) => match v.first() {
+            Some(d) => (format!("V:{}:{}:{}", strip_uvci(&d.id), d.dose, d.series), d.targeted.clone(), d.issuer.clone()),
+            None => return Err(Error::InvalidPass("certificate has no vaccination entries"))
+        },
+        EUDigitalGreenCertGroup::Test(t) => match t.first() {
+            Some(d) => (format!("T:{}", strip_uvci(&d.id)), d.targeted.clone(), d.issuer.clone()),
+            None => return Err(Error::InvalidPass("certificate has no test entries"))
+        }
+        EUDigitalGreenCertGroup::Recovery(r) => match r.first() {
+            Some(d) => (format!("R:{}", strip_uvci(&d.id)), d.targeted.clone(), d.issuer.clone()),
+            None => return Err(Error::InvalidPass("certificate has no recovery entries"))
+        }
+        EUDigitalGreenCertGroup::Exemption(e) => match e.first() {
+            Some(d) => (format!("E:{}", strip_uvci(&d.id)), d.targeted.clone(), d.issuer.clone()),
+            None => return Err(Error::InvalidPass("certificate has no exemption entries"))
+        }
+    };
+
+    let name = &payload.hcert.eu_dgc_v1.name;
+    let display_name = name.display_name();
+    if display_name.is_empty() {
+        return Err(Error::InvalidPass("certificate has no holder name"));
+    }
+    let icao_name = name.icao_name();
+
+    let mut aux_fields = vec![];
+    let mut back_fields = vec![PassField::text("name", "Name", name.native_name().unwrap_or_else(|| display_name.clone()))];
+    if let Some(icao_name) = &icao_name {
+        back_fields.push(PassField::text("icao-name", "Name (ICAO transliteration)", icao_name.clone()));
+    }
+    back_fields.push(PassField::date_time("exp", "Valid until", &payload.exp));
+    back_fields.push(PassField::text("iss", "Issued by", issued_by.clone()));
+    let mut secondary_fields = vec![];
+    if let Some(icao_name) = &icao_name {
+        secondary_fields.push(PassField::text("fnt", "ICAO name", icao_name.clone()));
+    }
+    secondary_fields.push(match payload.hcert.eu_dgc_v1.dob.date() {
+        Some(dob) => PassField::date("dob", "Date of Birth", dob),
+        None => PassField::text("dob", "Date of Birth", payload.hcert.eu_dgc_v1.dob.to_string()),
+    });
+
+    match &payload.hcert.eu_dgc_v1.group {
+        EUDigitalGreenCertGroup::Vaccination(v) => for (i, d) in v.iter().enumerate() {
+            if i > 0 {
+                back_fields.push(PassField::text(&format!("v{}", i + 1), &format!("Vaccination {}", i + 1), format!(
+                    "{}\n{}\nDose {} of {}\n{}",
+                    d.date.format("%Y-%m-%d"), d.product.display, d.dose, d.series, d.country.display
+                )));
+                continue;
+            }
+            aux_fields.push(PassField::text("vc", "Vaccine", d.vaccine.display.clone())
+                .translated(d.vaccine.translations.clone()));
+            secondary_fields.push(PassField::text("dose", "Dose", format!("{} of {}", d.dose, d.series)));
+            aux_fields.push(PassField::date("dt", "Date of Vaccination", d.date));
+            back_fields.push(PassField::text("mn", "Manufacturer", d.manufacturer.display.clone())
+                .translated(d.manufacturer.translations.clone()));
+            back_fields.push(PassField::text("pd", "Product", d.product.display.clone())
+                .translated(d.product.translations.clone()));
+            back_fields.push(PassField::text("co", "Country", d.country.display.clone())
+                .translated(d.country.translations.clone()));
+        }
+        EUDigitalGreenCertGroup::Test(t) => for (i, d) in t.iter().enumerate() {
+            if i > 0 {
+                back_fields.push(PassField::text(&format!("t{}", i + 1), &format!("Test {}", i + 1), format!(
+                    "{}\n{}\n{}\n{}",
+                    d.sample_time, d.test_type.display, d.result.display, d.country.display
+                )));
+                continue;
+            }
+            secondary_fields.push(PassField::text("tr", "Result", d.result.display.clone())
+                .translated(d.result.translations.clone()));
+            aux_fields.push(PassField::date_or_date_time("dt", "Date of test", &d.sample_time));
+            if let Some(result_time) = &d.result_time {
+                back_fields.push(PassField::date_or_date_time("dr", "Date of result", result_time));
+            }
+            back_fields.push(PassField::text("tt", "Test type", d.test_type.display.clone())
+                .translated(d.test_type.translations.clone()));
+            if let Some(nm) = &d.name {
+                back_fields.push(PassField::text("nm", "Test name", nm.clone()));
+            }
+            if let Some(tc) = &d.centre {
+                back_fields.push(PassField::text("tc", "Test centre", tc.clone()));
+            }
+            back_fields.push(PassField::text("co", "Country", d.country.display.clone())
+                .translated(d.country.translations.clone()));
+        }
+        EUDigitalGreenCertGroup::Recovery(r) => for (i, d) in r.iter().enumerate() {
+            if i > 0 {
+                back_fields.push(PassField::text(&format!("r{}", i + 1), &format!("Recovery {}", i + 1), format!(
+                    "Valid {} to {}\n{}",
+                    d.valid_from_date.format("%Y-%m-%d"), d.valid_until_date.format("%Y-%m-%d"), d.country.display
+                )));
+                continue;
+            }
+            aux_fields.push(PassField::date("df", "Valid from", d.valid_from_date));
+            aux_fields.push(PassField::date("du", "Valid until", d.valid_until_date));
+            back_fields.push(PassField::date("fr", "Date of first positive test", d.first_positive_test_date));
+            back_fields.push(PassField::text("co", "Country", d.country.display.clone())
+                .translated(d.country.translations.clone()));
+        }
+        EUDigitalGreenCertGroup::Exemption(e) => for (i, d) in e.iter().enumerate() {
+            if i > 0 {
+                back_fields.push(PassField::text(&format!("ex{}", i + 1), &format!("Exemption {}", i + 1), format!(
+                    "Valid {} to {}\n{}",
+                    d.valid_from_date.format("%Y-%m-%d"), d.valid_until_date.format("%Y-%m-%d"), d.country.display
+                )));
+                continue;
+            }
+            aux_fields.push(PassField::date("df", "Valid from", d.valid_from_date));
+            aux_fields.push(PassField::date("du", "Valid until", d.valid_until_date));
+            if let Some(es) = &d.status {
+                back_fields.push(PassField::text("es", "Exemption status", es.clone()));
+            }
+            back_fields.push(PassField::text("co", "Country", d.country.display.clone())
+                .translated(d.country.translations.clone()));
+        }
+    }
+
+    let (description, logo_text) = match &payload.hcert.eu_dgc_v1.group {
+        EUDigitalGreenCertGroup::Vaccination(_) => ("eHealth digital vaccination certificate", "Vaccination"),
+        EUDigitalGreenCertGroup::Test(_) => ("eHealth digital test certificate", "Test"),
+        EUDigitalGreenCertGroup::Recovery(_) => ("eHealth digital recovery certificate", "Recovery"),
+        EUDigitalGreenCertGroup::Exemption(_) => ("eHealth digital exemption certificate", "Exemption"),
+    };
+    let is_exemption = matches!(payload.hcert.eu_dgc_v1.group, EUDigitalGreenCertGroup::Exemption(_));
+
+    Ok(Pass {
+        serial,
+        description: description.to_string(),
+        org_name: issued_by,
+        logo_text: logo_text.to_string(),
+        colours: if is_exemption {
+            PassColours {
+                background: Rgb(255, 204, 0),
+                foreground: Rgb(0, 0, 0),
+                label: Rgb(0, 51, 153),
+            }
+        } else {
+            PassColours {
+                background: Rgb(0, 51, 153),
+                foreground: Rgb(255, 255, 255),
+                label: Rgb(255, 204, 0),
+            }
+        },
+        header_fields: vec![PassField::text("tg", "For", disease.display).translated(disease.translations)],
+        primary_fields: vec![PassField::text("fn", "Name", display_name)],
+        secondary_fields,
+        aux_fields,
+        back_fields,
+        barcode: msg,
+        exp_date: Some(payload.exp),
+    })
+}
+
+pub fn turkey_payload_to_pass(msg: String) -> Result<Pass, Error> {
+    let serial = match msg.strip_prefix("https://covidasidogrulama.saglik.gov.tr/api/CovidAsiKartiDogrula?Guid=") {
+        Some(s) => s.to_string(),
+        None => return Err(Error::InvalidPass("invalid payload"))
+    };
+
+    Ok(Pass {
+        serial,
+        description: "Turkey vaccination certificate".to_string(),
+        org_name: "Government of Turkey".to_string(),
+        logo_text: "Vaccination".to_string(),
+        colours: PassColours {
+            background: Rgb(185, 232, 234),
+            foreground: Rgb(0, 0, 0),
+            label: Rgb(27, 182, 193),
+        },
+        header_fields: vec![PassField::text("tg", "For", "COVID-19")],
+        primary_fields: vec![PassField::text("iss", "Issued by", "Government of Turkey")],
+        secondary_fields: vec![],
+        aux_fields: vec![],
+        back_fields: vec![PassField::link("vc", "View certificate", msg.clone())],
+        barcode: msg,
+        exp_date: None,
+    })
+}
+
+pub fn turkey_hes_payload_to_pass(msg: String) -> Result<Pass, Error> {
+    let hes_code = match msg.split_once("|") {
+        Some(s) => s.1,
+        None => return Err(Error::InvalidPass("invalid payload"))
+    };
+
+    Ok(Pass {
+        serial: hes_code.to_string(),
+        description: "Turkey HES certificate".to_string(),
+        org_name: "Government of Turkey".to_string(),
+        logo_text: "HES Code".to_string(),
+        colours: PassColours {
+            background: Rgb(90, 168, 0),
+            foreground: Rgb(255, 255, 255),
+            label: Rgb(255, 87, 34),
+        },
+        header_fields: vec![],
+        primary_fields: vec![PassField::text(
+            "hes", "Code", format!("{}-{}-{}", &hes_code[0..4], &hes_code[4..8], &hes_code[8..]),
+        )],
+        secondary_fields: vec![PassField::text("iss", "Issued by", "Government of Turkey")],
+        aux_fields: vec![],
+        back_fields: vec![],
+        barcode: msg,
+        exp_date: None,
+    })
+}
+
+pub fn smart_health_card_to_pass(card: SmartHealthCard, msg: String) -> Result<Pass, Error> {
+    let patient = card.patient()?;
+    let immunizations = card.immunizations();
+    let latest = match immunizations.iter().max_by_key(|i| i.date()) {
+        Some(i) => *i,
+        None => return Err(Error::Fhir("No immunizations in FHIR bundle"))
+    };
+    let name = match patient.display_name() {
+        Some(n) => n,
+        None => return Err(Error::Fhir("No patient name in FHIR bundle"))
+    };
+
+    let serial = format!("SHC:{}", hex::encode(&openssl::sha::sha256(&card.signature)[..16]));
+
+    let mut secondary_fields = vec![];
+    if let Some(birth_date) = &patient.birth_date {
+        secondary_fields.push(match NaiveDate::parse_from_str(birth_date, "%Y-%m-%d") {
+            Ok(d) => PassField::date("dob", "Date of Birth", d),
+            Err(_) => PassField::text("dob", "Date of Birth", birth_date.clone()),
+        });
+    }
+    secondary_fields.push(PassField::text("doses", "Doses", immunizations.len().to_string()));
+
+    let mut aux_fields = vec![PassField::text("vc", "Vaccine", latest.vaccine_display())];
+    if let Some(date) = latest.date() {
+        aux_fields.push(PassField::date("dt", "Date of Vaccination", date));
+    }
+
+    let mut back_fields = vec![];
+    for (i, immunization) in immunizations.iter().enumerate() {
+        let mut details = vec![
+            immunization.date().map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| immunization.occurrence.clone()),
+            immunization.vaccine_display(),
+        ];
+        if let Some(performer) = immunization.performer_display() {
+            details.push(performer.to_string());
+        }
+        if let Some(lot) = &immunization.lot_number {
+            details.push(format!("Lot {}", lot));
+        }
+        back_fields.push(PassField::text(&format!("dose{}", i + 1), &format!("Dose {}", i + 1), details.join("\n")));
+    }
+    back_fields.push(PassField::link("iss", "Issued by", card.payload.iss.clone()));
+
+    Ok(Pass {
+        serial,
+        description: "SMART Health Card vaccination record".to_string(),
+        org_name: "SMART Health Card".to_string(),
+        logo_text: "Vaccination".to_string(),
+        colours: PassColours {
+            background: Rgb(29, 53, 87),
+            foreground: Rgb(255, 255, 255),
+            label: Rgb(168, 218, 220),
+        },
+        header_fields: vec![PassField::text("tg", "For", "COVID-19")],
+        primary_fields: vec![PassField::text("fn", "Name", name)],
+        secondary_fields,
+        aux_fields,
+        back_fields,
+        barcode: msg,
+        exp_date: None,
+    })
+}
+
+pub fn nzcp_payload_to_pass(nzcp: NZCPCertificate, msg: String) -> Result<Pass, Error> {
+    let payload = nzcp.payload;
+
+    Ok(Pass {
+        serial: format!("NZCP:{}", hex::encode(&payload.cti)),
+        description: "NZ COVID Pass".to_string(),
+        org_name: "New Zealand Ministry of Health".to_string(),
+        logo_text: "My Vaccine Pass".to_string(),
+        colours: PassColours {
+            background: Rgb(0, 0, 0),
+            foreground: Rgb(255, 255, 255),
+            label: Rgb(170, 170, 170),
+        },
+        header_fields: vec![PassField::text("tg", "For", "COVID-19")],
+        primary_fields: vec![PassField::text("fn", "Name", payload.vc.subject.display_name())],
+        secondary_fields: vec![PassField::date("dob", "Date of Birth", payload.vc.subject.dob)],
+        aux_fields: vec![
+            PassField::date_of("nbf", "Valid from", &payload.nbf),
+            PassField::date_of("exp", "Valid until", &payload.exp),
+        ],
+        back_fields: vec![PassField::text("iss", "Issued by", payload.iss)],
+        barcode: msg,
+        exp_date: Some(payload.exp),
+    })
+}
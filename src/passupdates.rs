@@ -0,0 +1,566 @@
+use chrono::prelude::*;
+use rusqlite::OptionalExtension;
+use crate::dcc::EUDigitalGreenCertGroup;
+use crate::decode::HC1Certificate;
+use crate::error::{Error, PassStoreError};
+use crate::pkpass::{PKPass, PKPassSigningKeys};
+use crate::verify::{PassSigningCerts, RevocationHashType, Verification};
+
+const APNS_URL: &str = "https://api.push.apple.com/3/device/";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS passes (
+    pass_type_id TEXT NOT NULL,
+    serial TEXT NOT NULL,
+    description TEXT NOT NULL,
+    org_name TEXT NOT NULL,
+    kid BLOB NOT NULL,
+    revocation_hashes TEXT NOT NULL,
+    holder_hash BLOB NOT NULL,
+    issued_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    void_reason TEXT,
+    PRIMARY KEY (pass_type_id, serial)
+);
+CREATE INDEX IF NOT EXISTS passes_holder_hash ON passes (holder_hash);
+CREATE TABLE IF NOT EXISTS secrets (
+    name TEXT NOT NULL PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS registrations (
+    device_id TEXT NOT NULL,
+    pass_type_id TEXT NOT NULL,
+    serial TEXT NOT NULL,
+    push_token TEXT NOT NULL,
+    PRIMARY KEY (device_id, pass_type_id, serial)
+);
+";
+
+/// What is kept about an issued pass so it can later be voided: the revocation hashes of the certificate, and a keyed
+/// hash of the holder and the vaccination, test, recovery or exemption it is for to find the certificates a re-issued
+/// one supersedes.
+pub struct PassUpdateMetadata {
+    kid: Vec<u8>,
+    revocation_hashes: Vec<(RevocationHashType, Vec<u8>)>,
+    holder: String,
+    issued_at: DateTime<Utc>,
+}
+
+impl PassUpdateMetadata {
+    /// Only certificates with a verified signature are kept, as an unsigned certificate could otherwise be forged to
+    /// supersede, and so void, someone else's pass.
+    pub fn for_hc1(hc1: &HC1Certificate, verification: Verification) -> Result<Option<Self>, Error> {
+        if verification != Verification::Verified {
+            return Ok(None);
+        }
+
+        let payload = &hc1.payload;
+        let cert = &payload.hcert.eu_dgc_v1;
+        Ok(Some(PassUpdateMetadata {
+            kid: crate::verify::cose_key_id(&hc1.cose).to_vec(),
+            revocation_hashes: crate::verify::revocation_hashes(&hc1.cose, payload).map_err(Error::RevocationCheck)?,
+            holder: format!(
+                "{}\n{}\n{}\n{}\n{}",
+                payload.iss.to_uppercase(),
+                cert.name.std_surname.as_deref().unwrap_or_default(),
+                cert.name.std_forname.as_deref().unwrap_or_default(),
+                cert.dob,
+                supersede_event(&cert.group)?
+            ),
+            issued_at: payload.iat,
+        }))
+    }
+}
+
+/// A certificate supersedes an earlier issued one from the same country for the same holder only if it is of the same
+/// type and for the same event: the same dose of a vaccination, a test of the same sample time, a recovery from the
+/// same first positive test, or an exemption starting on the same date. Any other certificate, such as a later test or
+/// the next dose, is a separate certificate the holder may still need and leaves the earlier pass alone.
+fn supersede_event(group: &EUDigitalGreenCertGroup) -> Result<String, Error> {
+    match group {
+        EUDigitalGreenCertGroup::Vaccination(v) => v.first()
+            .map(|d| format!("V:{}:{}", d.targeted.display, d.dose)),
+        EUDigitalGreenCertGroup::Test(t) => t.first()
+            .map(|d| format!("T:{}:{}", d.targeted.display, d.sample_time)),
+        EUDigitalGreenCertGroup::Recovery(r) => r.first()
+            .map(|d| format!("R:{}:{}", d.targeted.display, d.first_positive_test_date)),
+        EUDigitalGreenCertGroup::Exemption(e) => e.first()
+            .map(|d| format!("E:{}:{}", d.targeted.display, d.valid_from_date)),
+    }.ok_or(Error::InvalidPass("certificate has no entries"))
+}
+
+pub struct StoredPass {
+    pub description: String,
+    pub org_name: String,
+    pub updated_at: DateTime<Utc>,
+    pub void_reason: Option<String>,
+}
+
+impl StoredPass {
+    /// The status to show on the updated pass, or `None` if the copy the device fetched at `if_modified_since` is
+    /// current. Only voided passes are ever updated, the certificate itself isn't stored so an active pass can't be
+    /// rebuilt.
+    pub fn update_status(&self, if_modified_since: Option<DateTime<FixedOffset>>) -> Option<&'static str> {
+        let status = match self.void_reason.as_deref() {
+            Some("revoked") => "This certificate has been revoked",
+            Some(_) => "This certificate has been replaced by a newer one",
+            None => return None
+        };
+        match if_modified_since {
+            // HTTP dates only have second precision
+            Some(since) if since.timestamp() >= self.updated_at.timestamp() => None,
+            _ => Some(status)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApnsClient {
+    client: reqwest::Client,
+    topic: String,
+}
+
+impl ApnsClient {
+    /// Pass update pushes are authenticated with the pass type certificate
+    pub fn new(signing_keys: &PKPassSigningKeys) -> Result<Self, PassStoreError> {
+        let pkcs12 = openssl::pkcs12::Pkcs12::builder()
+            .build("passkit", "passkit", &signing_keys.private_key, &signing_keys.public_cert)?
+            .to_der()?;
+        let identity = reqwest::Identity::from_pkcs12_der(&pkcs12, "passkit")?;
+        let client = reqwest::Client::builder()
+            .identity(identity)
+            .build()?;
+        Ok(ApnsClient {
+            client,
+            topic: crate::pkpass::PASS_TYPE_ID.to_string(),
+        })
+    }
+
+    async fn push(&self, push_token: &str) -> Result<(), String> {
+        let resp = self.client.post(format!("{}{}", APNS_URL, push_token))
+            .header("apns-topic", &self.topic)
+            .body("{}")
+            .send().await
+            .map_err(|e| format!("Unable to send push: {}", e))?;
+        if !resp.status().is_success() {
+            return Err(format!("APNs responded with {}", resp.status()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct PassUpdateService {
+    db: std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>,
+    token_key: std::sync::Arc<Vec<u8>>,
+    holder_key: std::sync::Arc<Vec<u8>>,
+    serial_key: std::sync::Arc<Vec<u8>>,
+    web_service_url: String,
+    apns: ApnsClient,
+}
+
+fn db_error(e: rusqlite::Error) -> Error {
+    Error::PassStore(PassStoreError::Database(e))
+}
+
+/// Derives a separate HMAC key for each use of the store secret with HKDF-SHA256.
+fn derive_key(secret: &[u8], info: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let mut ctx = openssl::pkey_ctx::PkeyCtx::new_id(openssl::pkey::Id::HKDF)?;
+    ctx.derive_init()?;
+    ctx.set_hkdf_md(openssl::md::Md::sha256())?;
+    ctx.set_hkdf_key(secret)?;
+    ctx.add_hkdf_info(info)?;
+    let mut key = vec![0; 32];
+    ctx.derive(Some(&mut key))?;
+    Ok(key)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let sign = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let key = openssl::pkey::PKey::hmac(key)?;
+        let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
+        signer.update(data)?;
+        signer.sign_to_vec()
+    };
+    sign().map_err(|e| Error::PassStore(PassStoreError::Crypto(e)))
+}
+
+impl PassUpdateService {
+    /// Opens the SQLCipher encrypted store at `path`, creating it if needed. The passphrase only unlocks the store,
+    /// the keys for authentication tokens and holder hashes are derived from a random secret kept inside it.
+    pub fn open(path: &str, key: &str, web_service_url: &str, apns: ApnsClient) -> Result<Self, PassStoreError> {
+        let db = rusqlite::Connection::open(path)?;
+        db.pragma_update(None, "key", key)?;
+        db.execute_batch(SCHEMA)?;
+
+        let mut secret = [0; 32];
+        openssl::rand::rand_bytes(&mut secret)?;
+        db.execute("INSERT OR IGNORE INTO secrets (name, value) VALUES ('master', ?1)", [&secret[..]])?;
+        let secret: Vec<u8> = db.query_row("SELECT value FROM secrets WHERE name = 'master'", [], |row| row.get(0))?;
+
+        Ok(PassUpdateService {
+            db: std::sync::Arc::new(std::sync::Mutex::new(db)),
+            token_key: std::sync::Arc::new(derive_key(&secret, b"authentication token")?),
+            holder_key: std::sync::Arc::new(derive_key(&secret, b"holder hash")?),
+            serial_key: std::sync::Arc::new(derive_key(&secret, b"pass serial")?),
+            web_service_url: web_service_url.to_string(),
+            apns,
+        })
+    }
+
+    pub fn web_service_url(&self) -> &str {
+        &self.web_service_url
+    }
+
+    /// Authentication tokens are derived from the serial, so rescanning a certificate gives the same token.
+    fn authentication_token(&self, pass_type_id: &str, serial: &str) -> Result<String, Error> {
+        Ok(hex::encode(hmac(&self.token_key, format!("{}\n{}", pass_type_id, serial).as_bytes())?))
+    }
+
+    pub fn is_authorised(&self, pass_type_id: &str, serial: &str, token: &str) -> Result<bool, Error> {
+        let expected = self.authentication_token(pass_type_id, serial)?;
+        if expected.len() != token.len() || !openssl::memcmp::eq(expected.as_bytes(), token.as_bytes()) {
+            return Ok(false);
+        }
+        Ok(self.pass(pass_type_id, serial)?.is_some())
+    }
+
+    /// Stores the pass, points it at the web service, and voids any older pass it supersedes.
+    ///
+    /// The serial is replaced by a keyed hash of it, as it is a path segment of the web service URLs and EU DCC
+    /// serials are UVCIs, which contain `/` and identify the certificate.
+    pub fn register_pass(&self, pass: &mut PKPass, metadata: PassUpdateMetadata) -> Result<(), Error> {
+        pass.serial = hex::encode(hmac(&self.serial_key, pass.serial.as_bytes())?);
+        pass.web_service_url = Some(self.web_service_url.clone());
+        pass.authentication_token = Some(self.authentication_token(&pass.type_id, &pass.serial)?);
+
+        let holder_hash = hmac(&self.holder_key, metadata.holder.as_bytes())?;
+        let revocation_hashes = serde_json::to_string(
+            &metadata.revocation_hashes.iter().map(|(t, h)| (*t, hex::encode(h))).collect::<Vec<_>>()
+        ).map_err(|e| Error::PassStore(PassStoreError::Json(e)))?;
+        let now = Utc::now().timestamp_millis();
+
+        let superseded = {
+            let db = self.db.lock().unwrap();
+            db.execute(
+                "INSERT OR IGNORE INTO passes (pass_type_id, serial, description, org_name, kid, revocation_hashes, \
+                holder_hash, issued_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                rusqlite::params![
+                    pass.type_id, pass.serial, pass.description, pass.org_name, metadata.kid, revocation_hashes,
+                    holder_hash, metadata.issued_at.timestamp(), now
+                ],
+            ).map_err(db_error)?;
+
+            // A certificate re-issued with the same UVCI has the same serial, and replaces the pass on the device
+            let mut stmt = db.prepare(
+                "SELECT pass_type_id, serial FROM passes WHERE holder_hash = ?1 AND issued_at < ?2 \
+                AND void_reason IS NULL AND NOT (pass_type_id = ?3 AND serial = ?4)"
+            ).map_err(db_error)?;
+            let superseded = stmt.query_map(
+                rusqlite::params![holder_hash, metadata.issued_at.timestamp(), pass.type_id, pass.serial],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            ).map_err(db_error)?.collect::<Result<Vec<_>, _>>().map_err(db_error)?;
+
+            for (pass_type_id, serial) in &superseded {
+                db.execute(
+                    "UPDATE passes SET void_reason = 'superseded', updated_at = ?1 WHERE pass_type_id = ?2 AND serial = ?3",
+                    rusqlite::params![now, pass_type_id, serial],
+                ).map_err(db_error)?;
+            }
+            superseded
+        };
+
+        if !superseded.is_empty() {
+            info!("Voided {} passes superseded by {}", superseded.len(), pass.serial);
+            self.notify(&superseded)?;
+        }
+        Ok(())
+    }
+
+    pub fn pass(&self, pass_type_id: &str, serial: &str) -> Result<Option<StoredPass>, Error> {
+        let db = self.db.lock().unwrap();
+        db.query_row(
+            "SELECT description, org_name, updated_at, void_reason FROM passes WHERE pass_type_id = ?1 AND serial = ?2",
+            rusqlite::params![pass_type_id, serial],
+            |row| {
+                let updated_at = row.get(2)?;
+                Ok(StoredPass {
+                    description: row.get(0)?,
+                    org_name: row.get(1)?,
+                    updated_at: Utc.timestamp_millis_opt(updated_at).single()
+                        .ok_or(rusqlite::Error::IntegralValueOutOfRange(2, updated_at))?,
+                    void_reason: row.get(3)?,
+                })
+            },
+        ).optional().map_err(db_error)
+    }
+
+    /// Returns whether this is a new registration
+    pub fn register_device(&self, device_id: &str, pass_type_id: &str, serial: &str, push_token: &str) -> Result<bool, Error> {
+        let db = self.db.lock().unwrap();
+        let existing = db.query_row(
+            "SELECT 1 FROM registrations WHERE device_id = ?1 AND pass_type_id = ?2 AND serial = ?3",
+            rusqlite::params![device_id, pass_type_id, serial],
+            |_| Ok(()),
+        ).optional().map_err(db_error)?;
+        db.execute(
+            "INSERT OR REPLACE INTO registrations (device_id, pass_type_id, serial, push_token) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![device_id, pass_type_id, serial, push_token],
+        ).map_err(db_error)?;
+        Ok(existing.is_none())
+    }
+
+    pub fn unregister_device(&self, device_id: &str, pass_type_id: &str, serial: &str) -> Result<(), Error> {
+        let db = self.db.lock().unwrap();
+        db.execute(
+            "DELETE FROM registrations WHERE device_id = ?1 AND pass_type_id = ?2 AND serial = ?3",
+            rusqlite::params![device_id, pass_type_id, serial],
+        ).map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Serials registered to the device that changed after the `since` update tag, and the latest update tag
+    pub fn updated_serials(
+        &self, device_id: &str, pass_type_id: &str, since: Option<i64>,
+    ) -> Result<Option<(Vec<String>, i64)>, Error> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(
+            "SELECT p.serial, p.updated_at FROM registrations r JOIN passes p \
+            ON p.pass_type_id = r.pass_type_id AND p.serial = r.serial \
+            WHERE r.device_id = ?1 AND r.pass_type_id = ?2 AND p.updated_at > ?3"
+        ).map_err(db_error)?;
+        let updated = stmt.query_map(
+            rusqlite::params![device_id, pass_type_id, since.unwrap_or(i64::MIN)],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+        ).map_err(db_error)?.collect::<Result<Vec<_>, _>>().map_err(db_error)?;
+
+        let last_updated = match updated.iter().map(|(_, u)| *u).max() {
+            Some(u) => u,
+            None => return Ok(None)
+        };
+        Ok(Some((updated.into_iter().map(|(s, _)| s).collect(), last_updated)))
+    }
+
+    /// Voids every stored pass whose certificate is on a revocation list
+    pub fn void_revoked(&self, signing_certs: &PassSigningCerts) -> Result<(), Error> {
+        let now = Utc::now().timestamp_millis();
+        let revoked = {
+            let db = self.db.lock().unwrap();
+            let mut stmt = db.prepare(
+                "SELECT pass_type_id, serial, kid, revocation_hashes FROM passes WHERE void_reason IS NULL"
+            ).map_err(db_error)?;
+            let passes = stmt.query_map([], |row| Ok((
+                row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Vec<u8>>(2)?, row.get::<_, String>(3)?
+            ))).map_err(db_error)?.collect::<Result<Vec<_>, _>>().map_err(db_error)?;
+
+            let mut revoked = vec![];
+            for (pass_type_id, serial, kid, revocation_hashes) in passes {
+                let revocation_hashes: Vec<(RevocationHashType, String)> = serde_json::from_str(&revocation_hashes)
+                    .map_err(|e| Error::PassStore(PassStoreError::Json(e)))?;
+                let is_revoked = revocation_hashes.iter().any(|(t, h)| match hex::decode(h) {
+                    Ok(h) => signing_certs.is_revoked(&kid, *t, &h),
+                    Err(_) => false
+                });
+                if is_revoked {
+                    db.execute(
+                        "UPDATE passes SET void_reason = 'revoked', updated_at = ?1 WHERE pass_type_id = ?2 AND serial = ?3",
+                        rusqlite::params![now, pass_type_id, serial],
+                    ).map_err(db_error)?;
+                    revoked.push((pass_type_id, serial));
+                }
+            }
+            revoked
+        };
+
+        if !revoked.is_empty() {
+            info!("Voided {} revoked passes", revoked.len());
+            self.notify(&revoked)?;
+        }
+        Ok(())
+    }
+
+    /// Asks every device registered for the passes to fetch them again
+    fn notify(&self, passes: &[(String, String)]) -> Result<(), Error> {
+        let mut push_tokens = std::collections::HashSet::new();
+        {
+            let db = self.db.lock().unwrap();
+            let mut stmt = db.prepare(
+                "SELECT push_token FROM registrations WHERE pass_type_id = ?1 AND serial = ?2"
+            ).map_err(db_error)?;
+            for (pass_type_id, serial) in passes {
+                for push_token in stmt.query_map(rusqlite::params![pass_type_id, serial], |row| row.get::<_, String>(0))
+                    .map_err(db_error)? {
+                    push_tokens.insert(push_token.map_err(db_error)?);
+                }
+            }
+        }
+
+        if push_tokens.is_empty() {
+            return Ok(());
+        }
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(r) => r,
+            Err(_) => {
+                warn!("Unable to notify {} devices of pass updates outside of a runtime", push_tokens.len());
+                return Ok(());
+            }
+        };
+        let apns = self.apns.clone();
+        runtime.spawn(async move {
+            for push_token in push_tokens {
+                if let Err(e) = apns.push(&push_token).await {
+                    warn!("Unable to notify device of pass update: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_cbor::Value;
+    use crate::decode::{decode_hc1, DecodeMode};
+    use crate::test_support::{self, text, with_entry_field, KeyIdLocation};
+
+    const ISSUED_AT: i64 = 1622316073;
+
+    fn service() -> PassUpdateService {
+        let apns = ApnsClient::new(&test_support::pass_signing_keys()).unwrap();
+        PassUpdateService::open(":memory:", "passphrase", "https://example.com/passkit", apns).unwrap()
+    }
+
+    fn hc1(iat: i64, eu_dgc_v1: Value) -> HC1Certificate {
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", iat, eu_dgc_v1), &test_support::signing_key(), b"kid", KeyIdLocation::Protected,
+        );
+        decode_hc1(&data, DecodeMode::Strict).unwrap()
+    }
+
+    fn register(service: &PassUpdateService, hc1: HC1Certificate) -> PKPass {
+        let metadata = PassUpdateMetadata::for_hc1(&hc1, Verification::Verified).unwrap().unwrap();
+        let mut pass = crate::pkpass::pass_to_pkpass(
+            &crate::pass::ehealth_payload_to_pass(hc1.payload, "HC1:".to_string()).unwrap()
+        );
+        service.register_pass(&mut pass, metadata).unwrap();
+        pass
+    }
+
+    fn void_reason(service: &PassUpdateService, pass: &PKPass) -> Option<String> {
+        service.pass(&pass.type_id, &pass.serial).unwrap().unwrap().void_reason
+    }
+
+    #[test]
+    fn only_registers_verified_certificates() {
+        let cert = hc1(ISSUED_AT, test_support::vaccination(1));
+        assert!(PassUpdateMetadata::for_hc1(&cert, Verification::Unverified).unwrap().is_none());
+        assert!(PassUpdateMetadata::for_hc1(&cert, Verification::Verified).unwrap().is_some());
+    }
+
+    #[test]
+    fn registered_passes_are_authorised_by_their_token() {
+        let service = service();
+        let pass = register(&service, hc1(ISSUED_AT, test_support::vaccination(1)));
+        assert_eq!(pass.web_service_url.as_deref(), Some("https://example.com/passkit"));
+        assert_eq!(pass.serial.len(), 64);
+        assert!(pass.serial.chars().all(|c| c.is_ascii_hexdigit()));
+        let token = pass.authentication_token.clone().unwrap();
+
+        assert!(service.is_authorised(&pass.type_id, &pass.serial, &token).unwrap());
+        assert!(!service.is_authorised(&pass.type_id, &pass.serial, &"0".repeat(token.len())).unwrap());
+        assert!(!service.is_authorised(&pass.type_id, &pass.serial, "").unwrap());
+
+        let other_serial = "V:01DE/IZ12345A/OTHER:1:2";
+        let other_token = service.authentication_token(&pass.type_id, other_serial).unwrap();
+        assert_ne!(other_token, token);
+        assert!(!service.is_authorised(&pass.type_id, other_serial, &other_token).unwrap());
+    }
+
+    #[test]
+    fn reissued_certificates_supersede_the_same_dose() {
+        let service = service();
+        let dose_1 = register(&service, hc1(ISSUED_AT, test_support::vaccination(1)));
+        let dose_2 = register(&service, hc1(ISSUED_AT + 10, test_support::vaccination(2)));
+        assert_eq!(void_reason(&service, &dose_1), None);
+
+        let reissued = register(&service, hc1(ISSUED_AT + 20, with_entry_field(
+            test_support::vaccination(1), "v", "ci", text("URN:UVCI:01DE/IZ12345A/REISSUED#1"),
+        )));
+        assert_eq!(void_reason(&service, &dose_1).as_deref(), Some("superseded"));
+        assert_eq!(void_reason(&service, &dose_2), None);
+        assert_eq!(void_reason(&service, &reissued), None);
+    }
+
+    #[test]
+    fn later_tests_do_not_supersede_earlier_tests() {
+        let service = service();
+        let first = register(&service, hc1(ISSUED_AT, test_support::test("2021-05-29T10:00:00Z")));
+        let second = register(&service, hc1(ISSUED_AT + 10, test_support::test("2021-05-31T10:00:00Z")));
+        assert_eq!(void_reason(&service, &first), None);
+
+        register(&service, hc1(ISSUED_AT + 20, with_entry_field(
+            test_support::test("2021-05-29T10:00:00Z"), "t", "ci", text("URN:UVCI:01DE/TZ12345A/REISSUED"),
+        )));
+        assert_eq!(void_reason(&service, &first).as_deref(), Some("superseded"));
+        assert_eq!(void_reason(&service, &second), None);
+    }
+
+    #[test]
+    fn rescanning_does_not_void_the_pass() {
+        let service = service();
+        let pass = register(&service, hc1(ISSUED_AT, test_support::vaccination(1)));
+        register(&service, hc1(ISSUED_AT, test_support::vaccination(1)));
+        let reissued = register(&service, hc1(ISSUED_AT + 10, test_support::vaccination(1)));
+        assert_eq!(reissued.serial, pass.serial);
+        assert_eq!(void_reason(&service, &pass), None);
+    }
+
+    #[test]
+    fn voids_revoked_passes() {
+        let service = service();
+        let cert = hc1(ISSUED_AT, test_support::vaccination(1));
+        let (hash_type, hash) = crate::verify::revocation_hashes(&cert.cose, &cert.payload).unwrap().into_iter()
+            .find(|(t, _)| *t == RevocationHashType::Uci).unwrap();
+        let revoked = register(&service, cert);
+        let active = register(&service, hc1(ISSUED_AT, test_support::vaccination(2)));
+        assert!(service.register_device("device", &revoked.type_id, &revoked.serial, "push-token").unwrap());
+        assert!(!service.register_device("device", &revoked.type_id, &revoked.serial, "push-token").unwrap());
+        let (_, registered_at) = service.updated_serials("device", &revoked.type_id, None).unwrap().unwrap();
+
+        let mut certs = PassSigningCerts::default();
+        certs.revoke(b"kid", hash_type, hash);
+        service.void_revoked(&certs).unwrap();
+
+        assert_eq!(void_reason(&service, &revoked).as_deref(), Some("revoked"));
+        assert_eq!(void_reason(&service, &active), None);
+        let (serials, _) = service.updated_serials("device", &revoked.type_id, Some(registered_at - 1)).unwrap().unwrap();
+        assert_eq!(serials, vec![revoked.serial.clone()]);
+
+        service.unregister_device("device", &revoked.type_id, &revoked.serial).unwrap();
+        assert!(service.updated_serials("device", &revoked.type_id, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_voided_passes_are_updated() {
+        let updated_at = Utc.with_ymd_and_hms(2021, 6, 1, 12, 0, 0).unwrap();
+        let mut stored = StoredPass {
+            description: "eHealth digital vaccination certificate".to_string(),
+            org_name: "Robert Koch-Institut".to_string(),
+            updated_at,
+            void_reason: None,
+        };
+        assert_eq!(stored.update_status(None), None);
+
+        stored.void_reason = Some("revoked".to_string());
+        assert_eq!(stored.update_status(None), Some("This certificate has been revoked"));
+        let before = (updated_at - chrono::Duration::seconds(1)).into();
+        assert_eq!(stored.update_status(Some(before)), Some("This certificate has been revoked"));
+        assert_eq!(stored.update_status(Some(updated_at.into())), None);
+        let http_date = DateTime::parse_from_rfc2822(&updated_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).unwrap();
+        assert_eq!(stored.update_status(Some(http_date)), None);
+
+        stored.void_reason = Some("superseded".to_string());
+        assert_eq!(stored.update_status(None), Some("This certificate has been replaced by a newer one"));
+    }
+}
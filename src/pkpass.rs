@@ -0,0 +1,481 @@
+use chrono::prelude::*;
+use std::io::Write;
+use crate::dcc::EHealthPayload;
+use crate::error::Error;
+use crate::nzcp::NZCPCertificate;
+use crate::pass::{self, Pass, PassField, PassFieldValue, Rgb};
+use crate::shc::SmartHealthCard;
+
+pub const PASS_TYPE_ID: &str = "pass.ch.magicalcodewit.pass.covid";
+pub const PASS_TEAM_ID: &str = "MQ9TN9772U";
+
+const PASS_ASSETS: include_dir::Dir = include_dir!("./pass-assets");
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PKPass {
+    pub description: String,
+    #[serde(rename = "formatVersion")]
+    pub format_version: u32,
+    #[serde(rename = "organizationName")]
+    pub org_name: String,
+    #[serde(rename = "passTypeIdentifier")]
+    pub type_id: String,
+    #[serde(rename = "serialNumber")]
+    pub serial: String,
+    #[serde(rename = "teamIdentifier")]
+    pub team_id: String,
+    #[serde(rename = "expirationDate", skip_serializing_if = "Option::is_none")]
+    pub exp_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub voided: bool,
+    #[serde(flatten)]
+    pub pass_style: PKPassStyle,
+    #[serde(rename = "backgroundColor", skip_serializing_if = "Option::is_none")]
+    pub bg_colour: Option<String>,
+    #[serde(rename = "foregroundColor", skip_serializing_if = "Option::is_none")]
+    pub fg_colour: Option<String>,
+    #[serde(rename = "labelColor", skip_serializing_if = "Option::is_none")]
+    pub label_colour: Option<String>,
+    #[serde(rename = "logoText", skip_serializing_if = "Option::is_none")]
+    pub logo_text: Option<String>,
+    #[serde(rename = "webServiceURL", skip_serializing_if = "Option::is_none")]
+    pub web_service_url: Option<String>,
+    #[serde(rename = "authenticationToken", skip_serializing_if = "Option::is_none")]
+    pub authentication_token: Option<String>,
+    #[serde(rename = "sharingProhibited", skip_serializing_if = "Option::is_none")]
+    pub sharing_prohibited: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub barcode: Option<PKPassBarcode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub barcodes: Vec<PKPassBarcode>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKPassStyle {
+    #[serde(rename = "boardingPass")]
+    BoardingPass(PKPassStructure),
+    #[serde(rename = "coupon")]
+    Coupon(PKPassStructure),
+    #[serde(rename = "eventTicket")]
+    EventTicket(PKPassStructure),
+    #[serde(rename = "generic")]
+    Generic(PKPassStructure),
+    #[serde(rename = "storeCard")]
+    StoreCard(PKPassStructure),
+}
+
+impl PKPassStyle {
+    pub fn structure_mut(&mut self) -> &mut PKPassStructure {
+        match self {
+            PKPassStyle::BoardingPass(s) | PKPassStyle::Coupon(s) | PKPassStyle::EventTicket(s) |
+            PKPassStyle::Generic(s) | PKPassStyle::StoreCard(s) => s
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PKPassStructure {
+    #[serde(rename = "auxiliaryFields", skip_serializing_if = "Vec::is_empty")]
+    pub aux_fields: Vec<PKPassField>,
+    #[serde(rename = "backFields", skip_serializing_if = "Vec::is_empty")]
+    pub back_fields: Vec<PKPassField>,
+    #[serde(rename = "headerFields", skip_serializing_if = "Vec::is_empty")]
+    pub header_fields: Vec<PKPassField>,
+    #[serde(rename = "primaryFields", skip_serializing_if = "Vec::is_empty")]
+    pub primary_fields: Vec<PKPassField>,
+    #[serde(rename = "secondaryFields", skip_serializing_if = "Vec::is_empty")]
+    pub secondary_fields: Vec<PKPassField>,
+}
+
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct PKPassField {
+    #[serde(rename = "attributedValue", skip_serializing_if = "Option::is_none")]
+    pub attributed_value: Option<String>,
+    #[serde(rename = "changeMessage", skip_serializing_if = "Option::is_none")]
+    pub change_message: Option<String>,
+    #[serde(rename = "dataDetectorTypes", skip_serializing_if = "Option::is_none")]
+    pub data_detectors: Option<Vec<PKDataDetector>>,
+    #[serde(rename = "textAlignment", skip_serializing_if = "Option::is_none")]
+    pub text_alignment: Option<PKTextAlignment>,
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub value: String,
+    #[serde(rename = "dateStyle", skip_serializing_if = "Option::is_none")]
+    pub date_style: Option<PKDateStyle>,
+    #[serde(rename = "timeStyle", skip_serializing_if = "Option::is_none")]
+    pub time_style: Option<PKDateStyle>,
+    #[serde(rename = "numberStyle", skip_serializing_if = "Option::is_none")]
+    pub number_style: Option<PKNumberStyle>,
+    #[serde(rename = "ignoresTimeZone", skip_serializing_if = "Option::is_none")]
+    pub ignores_time_zone: Option<bool>,
+    #[serde(rename = "isRelative", skip_serializing_if = "Option::is_none")]
+    pub is_relative: Option<bool>,
+    #[serde(rename = "currencyCode", skip_serializing_if = "Option::is_none")]
+    pub currency_code: Option<String>,
+    /// Translations of `value` by language code, written to the `pass.strings` bundles by `sign_pkpass`
+    #[serde(skip)]
+    pub value_translations: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKBarcodeFormat {
+    #[serde(rename = "PKBarcodeFormatQR")]
+    QR,
+    #[serde(rename = "PKBarcodeFormatPDF417")]
+    PDF417,
+    #[serde(rename = "PKBarcodeFormatAztec")]
+    Aztec,
+    #[serde(rename = "PKBarcodeFormatCode128")]
+    Code128,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKDataDetector {
+    #[serde(rename = "PKDataDetectorTypePhoneNumber")]
+    PhoneNumber,
+    #[serde(rename = "PKDataDetectorTypeLink")]
+    Link,
+    #[serde(rename = "PKDataDetectorTypeAddress")]
+    Address,
+    #[serde(rename = "PKDataDetectorTypeCalendarEvent")]
+    CalendarEvent,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKTextAlignment {
+    #[serde(rename = "PKTextAlignmentLeft")]
+    Left,
+    #[serde(rename = "PKTextAlignmentCenter")]
+    Center,
+    #[serde(rename = "PKTextAlignmentRight")]
+    Right,
+    #[serde(rename = "PKTextAlignmentNatural")]
+    Natural,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKDateStyle {
+    #[serde(rename = "PKDateStyleNone")]
+    None,
+    #[serde(rename = "PKDateStyleShort")]
+    Short,
+    #[serde(rename = "PKDateStyleMedium")]
+    Medium,
+    #[serde(rename = "PKDateStyleLong")]
+    Long,
+    #[serde(rename = "PKDateStyleFull")]
+    Full,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum PKNumberStyle {
+    #[serde(rename = "PKNumberStyleDecimal")]
+    Decimal,
+    #[serde(rename = "PKNumberStylePercent")]
+    Percent,
+    #[serde(rename = "PKNumberStyleScientific")]
+    Scientific,
+    #[serde(rename = "PKNumberStyleSpellOut")]
+    SpellOut,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PKPassBarcode {
+    #[serde(rename = "altText", skip_serializing_if = "Option::is_none")]
+    pub alt_text: Option<String>,
+    pub format: PKBarcodeFormat,
+    pub message: String,
+    #[serde(rename = "messageEncoding")]
+    pub message_encoding: String,
+}
+
+/// Renders a pass for Apple Wallet.
+pub fn pass_to_pkpass(pass: &Pass) -> PKPass {
+    let barcode = PKPassBarcode {
+        alt_text: None,
+        format: PKBarcodeFormat::QR,
+        message: pass.barcode.clone(),
+        message_encoding: "iso-8859-1".to_string(),
+    };
+
+    PKPass {
+        format_version: 1,
+        description: pass.description.clone(),
+        org_name: pass.org_name.clone(),
+        type_id: PASS_TYPE_ID.to_string(),
+        serial: pass.serial.clone(),
+        team_id: PASS_TEAM_ID.to_string(),
+        voided: false,
+        sharing_prohibited: Some(true),
+        pass_style: PKPassStyle::Generic(PKPassStructure {
+            aux_fields: pass.aux_fields.iter().map(pass_field_to_pkpass_field).collect(),
+            back_fields: pass.back_fields.iter().map(pass_field_to_pkpass_field).collect(),
+            header_fields: pass.header_fields.iter().map(pass_field_to_pkpass_field).collect(),
+            primary_fields: pass.primary_fields.iter().map(pass_field_to_pkpass_field).collect(),
+            secondary_fields: pass.secondary_fields.iter().map(pass_field_to_pkpass_field).collect(),
+        }),
+        bg_colour: Some(rgb_colour(pass.colours.background)),
+        fg_colour: Some(rgb_colour(pass.colours.foreground)),
+        label_colour: Some(rgb_colour(pass.colours.label)),
+        logo_text: Some(pass.logo_text.clone()),
+        web_service_url: None,
+        authentication_token: None,
+        exp_date: pass.exp_date,
+        barcode: Some(barcode.clone()),
+        barcodes: vec![barcode],
+    }
+}
+
+// Apple Wallet only shortcuts for each of the `pass` builders
+pub fn ehealth_payload_to_pkpass(payload: EHealthPayload, msg: String) -> Result<PKPass, Error> {
+    Ok(pass_to_pkpass(&pass::ehealth_payload_to_pass(payload, msg)?))
+}
+
+pub fn turkey_payload_to_pkpass(msg: String) -> Result<PKPass, Error> {
+    Ok(pass_to_pkpass(&pass::turkey_payload_to_pass(msg)?))
+}
+
+pub fn turkey_hes_payload_to_pkpass(msg: String) -> Result<PKPass, Error> {
+    Ok(pass_to_pkpass(&pass::turkey_hes_payload_to_pass(msg)?))
+}
+
+pub fn smart_health_card_to_pkpass(card: SmartHealthCard, msg: String) -> Result<PKPass, Error> {
+    Ok(pass_to_pkpass(&pass::smart_health_card_to_pass(card, msg)?))
+}
+
+pub fn nzcp_payload_to_pkpass(nzcp: NZCPCertificate, msg: String) -> Result<PKPass, Error> {
+    Ok(pass_to_pkpass(&pass::nzcp_payload_to_pass(nzcp, msg)?))
+}
+
+fn pass_field_to_pkpass_field(field: &PassField) -> PKPassField {
+    let field_base = PKPassField {
+        data_detectors: Some(if field.link { vec![PKDataDetector::Link] } else { vec![] }),
+        key: field.key.clone(),
+        label: Some(field.label.clone()),
+        value_translations: field.value_translations.clone(),
+        ..Default::default()
+    };
+    match &field.value {
+        PassFieldValue::Text(value) => PKPassField {
+            value: value.clone(),
+            ..field_base
+        },
+        PassFieldValue::Date(date) => PKPassField {
+            value: Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()).to_rfc3339(),
+            date_style: Some(PKDateStyle::Long),
+            time_style: Some(PKDateStyle::None),
+            ignores_time_zone: Some(true),
+            ..field_base
+        },
+        PassFieldValue::DateOf(date_time) => PKPassField {
+            value: date_time.to_rfc3339(),
+            date_style: Some(PKDateStyle::Long),
+            time_style: Some(PKDateStyle::None),
+            ..field_base
+        },
+        PassFieldValue::DateTime(date_time) => PKPassField {
+            value: date_time.to_rfc3339(),
+            date_style: Some(PKDateStyle::Long),
+            time_style: Some(PKDateStyle::Long),
+            ..field_base
+        },
+    }
+}
+
+fn rgb_colour(colour: Rgb) -> String {
+    format!("rgb({}, {}, {})", colour.0, colour.1, colour.2)
+}
+
+/// Builds the replacement for a pass that has been voided, keeping only the metadata held for pass updates.
+pub fn voided_pkpass(serial: String, description: String, org_name: String, status: &str) -> PKPass {
+    PKPass {
+        format_version: 1,
+        description,
+        org_name,
+        type_id: PASS_TYPE_ID.to_string(),
+        serial,
+        team_id: PASS_TEAM_ID.to_string(),
+        voided: true,
+        sharing_prohibited: Some(true),
+        pass_style: PKPassStyle::Generic(PKPassStructure {
+            aux_fields: vec![],
+            back_fields: vec![],
+            header_fields: vec![],
+            primary_fields: vec![PKPassField {
+                data_detectors: Some(vec![]),
+                key: "warning".to_string(),
+                label: Some("Status".to_string()),
+                value: status.to_string(),
+                ..Default::default()
+            }],
+            secondary_fields: vec![],
+        }),
+        bg_colour: Some("rgb(142, 142, 147)".to_string()),
+        fg_colour: Some("rgb(255, 255, 255)".to_string()),
+        label_colour: Some("rgb(0, 0, 0)".to_string()),
+        logo_text: Some("Certificate".to_string()),
+        web_service_url: None,
+        authentication_token: None,
+        exp_date: None,
+        barcode: None,
+        barcodes: vec![],
+    }
+}
+
+pub struct PKPassSigningKeys {
+    pub public_cert: openssl::x509::X509,
+    pub private_key: openssl::pkey::PKey<openssl::pkey::Private>,
+    pub intermediate_certs: openssl::stack::Stack<openssl::x509::X509>,
+}
+
+pub fn sign_pkpass(pass: &PKPass, signing_keys: &PKPassSigningKeys) -> Result<Vec<u8>, Error> {
+    let mut pass = pass.clone();
+    let localisations = crate::localisation::localise_pass(&mut pass);
+    let pass_bytes = serde_json::to_vec(&pass).map_err(|e| Error::Signing("Unable to serialize pass", e.into()))?;
+
+    let mut manifest = std::collections::HashMap::<String, String>::new();
+
+    let mut buf = vec![];
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(&mut buf));
+
+    archive.start_file("pass.json", zip::write::FileOptions::default())
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    archive.write_all(&pass_bytes)
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+
+    let pass_hash = hex::encode(
+        openssl::hash::hash(openssl::hash::MessageDigest::sha1(), &pass_bytes)
+            .map_err(|e| Error::Signing("Failed to calculate manifest", e.into()))?
+    );
+    manifest.insert("pass.json".to_string(), pass_hash);
+
+    for file in PASS_ASSETS.files() {
+        let file_path = file.path().to_string_lossy();
+        let file_contents = file.contents();
+        archive.start_file(file_path.to_string(), zip::write::FileOptions::default())
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+        archive.write_all(file_contents)
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+
+        let file_hash = hex::encode(
+            openssl::hash::hash(openssl::hash::MessageDigest::sha1(), file_contents)
+                .map_err(|e| Error::Signing("Failed to calculate manifest", e.into()))?
+        );
+        manifest.insert(file_path.to_string(), file_hash);
+    }
+
+    for (file_path, file_contents) in &localisations {
+        archive.start_file(file_path.as_str(), zip::write::FileOptions::default())
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+        archive.write_all(file_contents)
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+
+        let file_hash = hex::encode(
+            openssl::hash::hash(openssl::hash::MessageDigest::sha1(), file_contents)
+                .map_err(|e| Error::Signing("Failed to calculate manifest", e.into()))?
+        );
+        manifest.insert(file_path.clone(), file_hash);
+    }
+
+    let manifest_bytes = serde_json::to_vec(&manifest).map_err(|e| Error::Signing("Unable to serialize manifest", e.into()))?;
+
+    archive.start_file("manifest.json", zip::write::FileOptions::default())
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    archive.write_all(&manifest_bytes)
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+
+    let pkcs7 = openssl::pkcs7::Pkcs7::sign(
+        signing_keys.public_cert.as_ref(),
+        signing_keys.private_key.as_ref(),
+        signing_keys.intermediate_certs.as_ref(),
+        &manifest_bytes,
+        openssl::pkcs7::Pkcs7Flags::DETACHED | openssl::pkcs7::Pkcs7Flags::NOCRL,
+    ).map_err(|e| Error::Signing("Unable to sign manifest", e.into()))?;
+    let pkcs7_bytes = pkcs7.to_der().map_err(|e| Error::Signing("Unable to serialize signature", e.into()))?;
+
+    archive.start_file("signature", zip::write::FileOptions::default())
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    archive.write_all(&pkcs7_bytes)
+        .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+
+    archive.finish().map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    std::mem::drop(archive);
+
+    Ok(buf)
+}
+
+
+/// Zips already signed passes into a `.pkpasses` bundle so Wallet can add them all at once.
+pub fn bundle_pkpasses(passes: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+    if passes.is_empty() {
+        return Err(Error::InvalidPass("bundle contains no passes"));
+    }
+
+    let mut buf = vec![];
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(&mut buf));
+
+    for (i, pass) in passes.iter().enumerate() {
+        archive.start_file(format!("ehealth-{}.pkpass", i + 1), zip::write::FileOptions::default())
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+        archive.write_all(pass)
+            .map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    }
+
+    archive.finish().map_err(|e| Error::Signing("Failed to write ZIP file", e.into()))?;
+    std::mem::drop(archive);
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, KeyIdLocation};
+    use std::io::Read;
+
+    fn read_file(archive: &mut zip::ZipArchive<std::io::Cursor<Vec<u8>>>, name: &str) -> Vec<u8> {
+        let mut contents = vec![];
+        archive.by_name(name).unwrap().read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn signs_localised_pass_strings() {
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", 1622316073, test_support::vaccination(2)), &test_support::signing_key(), b"kid",
+            KeyIdLocation::Protected,
+        );
+        let hc1 = crate::decode::decode_hc1(&data, crate::decode::DecodeMode::Strict).unwrap();
+        let pass = ehealth_payload_to_pkpass(hc1.payload, data).unwrap();
+        let pkpass = sign_pkpass(&pass, &test_support::pass_signing_keys()).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(pkpass)).unwrap();
+
+        let manifest: std::collections::HashMap<String, String> =
+            serde_json::from_slice(&read_file(&mut archive, "manifest.json")).unwrap();
+        for lang in crate::localisation::PASS_LANGUAGES {
+            let path = format!("{}.lproj/pass.strings", lang);
+            let strings = read_file(&mut archive, &path);
+            assert_eq!(&strings[..2], &[0xFF, 0xFE]);
+            let hash = hex::encode(openssl::hash::hash(openssl::hash::MessageDigest::sha1(), &strings).unwrap());
+            assert_eq!(manifest.get(&path), Some(&hash), "{} is not in the manifest", path);
+        }
+
+        let pass_json = String::from_utf8(read_file(&mut archive, "pass.json")).unwrap();
+        assert!(!pass_json.contains("Date of Birth"));
+        let strings = read_file(&mut archive, "de.lproj/pass.strings");
+        let units = strings[2..].chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>();
+        assert!(String::from_utf16(&units).unwrap().contains("= \"Geburtsdatum\";"));
+    }
+
+    #[test]
+    fn bundles_signed_passes() {
+        let passes = vec![b"first pass".to_vec(), b"second pass".to_vec()];
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bundle_pkpasses(&passes).unwrap())).unwrap();
+        assert_eq!(archive.len(), 2);
+        assert_eq!(read_file(&mut archive, "ehealth-1.pkpass"), b"first pass");
+        assert_eq!(read_file(&mut archive, "ehealth-2.pkpass"), b"second pass");
+
+        assert!(matches!(bundle_pkpasses(&[]), Err(Error::InvalidPass(_))));
+    }
+}
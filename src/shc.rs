@@ -0,0 +1,487 @@
+use chrono::prelude::*;
+use std::io::Read;
+use crate::error::Error;
+
+#[derive(Debug)]
+pub struct SmartHealthCard {
+    pub header: JwsHeader,
+    pub payload: SmartHealthCardPayload,
+    pub signing_input: String,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JwsHeader {
+    pub alg: String,
+    #[serde(default)]
+    pub kid: Option<String>,
+    #[serde(default)]
+    pub zip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmartHealthCardPayload {
+    pub iss: String,
+    #[serde(default, deserialize_with = "de_numeric_date")]
+    pub nbf: Option<DateTime<Utc>>,
+    pub vc: VerifiableCredential,
+}
+
+fn de_numeric_date<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    use serde::de::Deserialize;
+    let t = f64::deserialize(d)?;
+    if !t.is_finite() || t < i64::MIN as f64 || t >= i64::MAX as f64 {
+        return Err(serde::de::Error::custom("nbf is out of range"));
+    }
+    Utc.timestamp_opt(t.trunc() as i64, 0).single()
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom("nbf is out of range"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifiableCredential {
+    #[serde(rename = "type")]
+    pub types: Vec<String>,
+    #[serde(rename = "credentialSubject")]
+    pub subject: CredentialSubject,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CredentialSubject {
+    #[serde(rename = "fhirVersion", default)]
+    pub fhir_version: Option<String>,
+    #[serde(rename = "fhirBundle")]
+    pub bundle: FhirBundle,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirBundle {
+    #[serde(default)]
+    pub entry: Vec<FhirBundleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirBundleEntry {
+    pub resource: FhirResource,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "resourceType")]
+pub enum FhirResource {
+    Patient(FhirPatient),
+    Immunization(FhirImmunization),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirPatient {
+    #[serde(default)]
+    pub name: Vec<FhirHumanName>,
+    #[serde(rename = "birthDate", default)]
+    pub birth_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirHumanName {
+    #[serde(default)]
+    pub family: Option<String>,
+    #[serde(default)]
+    pub given: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirImmunization {
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(rename = "vaccineCode")]
+    pub vaccine_code: FhirCodeableConcept,
+    #[serde(rename = "occurrenceDateTime")]
+    pub occurrence: String,
+    #[serde(default)]
+    pub performer: Vec<FhirPerformer>,
+    #[serde(rename = "lotNumber", default)]
+    pub lot_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirCodeableConcept {
+    #[serde(default)]
+    pub coding: Vec<FhirCoding>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirCoding {
+    #[serde(default)]
+    pub system: Option<String>,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirPerformer {
+    pub actor: FhirReference,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FhirReference {
+    #[serde(default)]
+    pub display: Option<String>,
+}
+
+impl SmartHealthCard {
+    pub fn patient(&self) -> Result<&FhirPatient, Error> {
+        self.payload.vc.subject.bundle.entry.iter()
+            .find_map(|e| match &e.resource {
+                FhirResource::Patient(p) => Some(p),
+                _ => None
+            })
+            .ok_or(Error::Fhir("No patient in FHIR bundle"))
+    }
+
+    pub fn immunizations(&self) -> Vec<&FhirImmunization> {
+        self.payload.vc.subject.bundle.entry.iter()
+            .filter_map(|e| match &e.resource {
+                FhirResource::Immunization(i) => Some(i),
+                _ => None
+            })
+            .filter(|i| i.status.as_deref().unwrap_or("completed") == "completed")
+            .collect()
+    }
+}
+
+impl FhirPatient {
+    pub fn display_name(&self) -> Option<String> {
+        self.name.first().map(|n| {
+            let mut parts = n.given.clone();
+            if let Some(family) = &n.family {
+                parts.push(family.clone());
+            }
+            parts.join(" ")
+        })
+    }
+}
+
+impl FhirImmunization {
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.occurrence.get(..10).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+    }
+
+    pub fn vaccine_display(&self) -> String {
+        match self.vaccine_code.coding.first() {
+            Some(c) => match c.system.as_deref() {
+                Some("http://hl7.org/fhir/sid/cvx") => cvx_display(&c.code)
+                    .map(|d| d.to_string())
+                    .unwrap_or_else(|| format!("CVX {}", c.code)),
+                _ => c.code.clone()
+            },
+            None => "Unknown vaccine".to_string()
+        }
+    }
+
+    pub fn performer_display(&self) -> Option<&str> {
+        self.performer.iter().find_map(|p| p.actor.display.as_deref())
+    }
+}
+
+fn cvx_display(code: &str) -> Option<&'static str> {
+    match code {
+        "207" | "221" => Some("Moderna COVID-19 Vaccine"),
+        "208" | "217" | "218" | "219" => Some("Pfizer-BioNTech COVID-19 Vaccine"),
+        "210" => Some("AstraZeneca COVID-19 Vaccine"),
+        "211" => Some("Novavax COVID-19 Vaccine"),
+        "212" => Some("Janssen COVID-19 Vaccine"),
+        "213" => Some("COVID-19 Vaccine"),
+        "510" => Some("Sinopharm COVID-19 Vaccine"),
+        "511" => Some("Sinovac COVID-19 Vaccine"),
+        _ => None
+    }
+}
+
+pub fn decode_shc_numeric(data: &str) -> Result<String, Error> {
+    let digits = data.strip_prefix("shc:/").ok_or(Error::Unsupported)?;
+    if digits.contains('/') {
+        return Err(Error::ShcEncoding("Multi-part SMART Health Cards must be scanned one part at a time"));
+    }
+    decode_numeric(digits)
+}
+
+fn decode_numeric(digits: &str) -> Result<String, Error> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::ShcEncoding("Invalid SMART Health Card numeric encoding"));
+    }
+
+    digits.as_bytes().chunks(2).map(|pair| {
+        let value = (pair[0] - b'0') * 10 + (pair[1] - b'0');
+        if value > 77 {
+            Err(Error::ShcEncoding("Invalid SMART Health Card numeric encoding"))
+        } else {
+            Ok((value + 45) as char)
+        }
+    }).collect()
+}
+
+pub fn parse_shc_chunk(data: &str) -> Result<(usize, usize, &str), Error> {
+    let mut parts = data.strip_prefix("shc:/").ok_or(Error::Unsupported)?.splitn(3, '/');
+    let (index, total, digits) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(i), Some(t), Some(d)) => (i, t, d),
+        _ => return Err(Error::ShcEncoding("Invalid SMART Health Card chunk"))
+    };
+    let index: usize = index.parse().map_err(|_| Error::ShcEncoding("Invalid SMART Health Card chunk"))?;
+    let total: usize = total.parse().map_err(|_| Error::ShcEncoding("Invalid SMART Health Card chunk"))?;
+    if total == 0 || total > MAX_SHC_CHUNKS || index == 0 || index > total {
+        return Err(Error::ShcEncoding("Invalid SMART Health Card chunk"));
+    }
+    decode_numeric(digits)?;
+    Ok((index, total, digits))
+}
+
+const HEALTH_CARD_TYPE: &str = "https://smarthealth.cards#health-card";
+const MAX_SHC_CHUNKS: usize = 20;
+const MAX_SHC_SESSIONS: usize = 10000;
+
+struct ShcChunkSession {
+    created: std::time::Instant,
+    chunks: Vec<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShcChunkStatus {
+    pub session: String,
+    pub received: usize,
+    pub total: usize,
+    pub complete: bool,
+}
+
+#[derive(Clone)]
+pub struct ShcChunkAssembler {
+    ttl: std::time::Duration,
+    sessions: std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, ShcChunkSession>>>,
+}
+
+impl ShcChunkAssembler {
+    pub fn new(ttl: std::time::Duration) -> Self {
+        ShcChunkAssembler {
+            ttl,
+            sessions: std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+        }
+    }
+
+    pub fn add_chunk(&self, session: Option<&str>, data: &str) -> Result<ShcChunkStatus, Error> {
+        let (index, total, digits) = parse_shc_chunk(data)?;
+        self.remove_expired();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let session_id = match session {
+            Some(s) => s.to_string(),
+            None => {
+                if sessions.len() >= MAX_SHC_SESSIONS {
+                    return Err(Error::ShcSession("Too many scans in progress, please try again later"));
+                }
+                let mut token = [0; 16];
+                openssl::rand::rand_bytes(&mut token)
+                    .map_err(|_| Error::ShcSession("Unable to start scan session"))?;
+                let session_id = hex::encode(token);
+                sessions.insert(session_id.clone(), ShcChunkSession {
+                    created: std::time::Instant::now(),
+                    chunks: vec![None; total],
+                });
+                session_id
+            }
+        };
+
+        let chunk_session = sessions.get_mut(&session_id)
+            .ok_or(Error::ShcSession("Scan session expired, please start again"))?;
+        if chunk_session.chunks.len() != total {
+            return Err(Error::ShcEncoding("SMART Health Card chunk belongs to a different card"));
+        }
+        chunk_session.chunks[index - 1] = Some(digits.to_string());
+
+        let received = chunk_session.chunks.iter().filter(|c| c.is_some()).count();
+        Ok(ShcChunkStatus {
+            session: session_id,
+            received,
+            total,
+            complete: received == total,
+        })
+    }
+
+    pub fn take(&self, session: &str) -> Result<String, Error> {
+        self.remove_expired();
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let complete = match sessions.get(session) {
+            Some(s) => s.chunks.iter().all(|c| c.is_some()),
+            None => return Err(Error::ShcSession("Scan session expired, please start again"))
+        };
+        if !complete {
+            return Err(Error::ShcSession("Not all parts of the SMART Health Card have been scanned"));
+        }
+
+        let chunk_session = sessions.remove(session).unwrap();
+        let digits: String = chunk_session.chunks.into_iter().flatten().collect();
+        Ok(format!("shc:/{}", digits))
+    }
+
+    pub fn remove_expired(&self) {
+        let ttl = self.ttl;
+        self.sessions.lock().unwrap().retain(|_, s| s.created.elapsed() < ttl);
+    }
+}
+
+pub fn decode_jws(jws: &str) -> Result<SmartHealthCard, Error> {
+    let mut parts = jws.split('.');
+    let (header_b64, payload_b64, signature_b64) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(p), Some(s), None) => (h, p, s),
+        _ => return Err(Error::ShcEncoding("Invalid JWS"))
+    };
+
+    let header_bytes = base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| Error::Jws("header", e.into()))?;
+    let header: JwsHeader = serde_json::from_slice(&header_bytes)
+        .map_err(|e| Error::Jws("header", e.into()))?;
+
+    let payload_deflated = base64::decode_config(payload_b64, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| Error::Jws("payload", e.into()))?;
+    let payload_bytes = if header.zip.as_deref() == Some("DEF") {
+        let mut deflater = flate2::read::DeflateDecoder::new(&payload_deflated[..]);
+        let mut payload_bytes = Vec::new();
+        deflater.read_to_end(&mut payload_bytes).map_err(Error::Inflate)?;
+        payload_bytes
+    } else {
+        payload_deflated
+    };
+    let payload: SmartHealthCardPayload = serde_json::from_slice(&payload_bytes)
+        .map_err(Error::Json)?;
+    if !payload.vc.types.iter().any(|t| t == HEALTH_CARD_TYPE) {
+        return Err(Error::ShcEncoding("Not a SMART Health Card"));
+    }
+
+    let signature = base64::decode_config(signature_b64, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| Error::Jws("signature", e.into()))?;
+
+    Ok(SmartHealthCard {
+        header,
+        payload,
+        signing_input: format!("{}.{}", header_b64, payload_b64),
+        signature,
+    })
+}
+
+pub fn decode_shc(data: &str) -> Result<SmartHealthCard, Error> {
+    let jws = decode_shc_numeric(data)?;
+    decode_jws(&jws)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jws(payload: serde_json::Value) -> String {
+        format!(
+            "{}.{}.{}",
+            base64::encode_config(br#"{"alg":"ES256","kid":"test"}"#, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(payload.to_string(), base64::URL_SAFE_NO_PAD),
+            base64::encode_config([0; 64], base64::URL_SAFE_NO_PAD),
+        )
+    }
+
+    fn payload(types: &[&str], nbf: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "iss": "https://spec.smarthealth.cards/examples/issuer",
+            "nbf": nbf,
+            "vc": {
+                "type": types,
+                "credentialSubject": {
+                    "fhirVersion": "4.0.1",
+                    "fhirBundle": {
+                        "resourceType": "Bundle",
+                        "entry": [],
+                    },
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn decodes_health_card() {
+        let card = decode_jws(&jws(payload(
+            &[HEALTH_CARD_TYPE, "https://smarthealth.cards#immunization"], serde_json::json!(1620847989.5),
+        ))).unwrap();
+        assert_eq!(card.payload.nbf.unwrap().timestamp(), 1620847989);
+        assert_eq!(card.signing_input.split('.').count(), 2);
+    }
+
+    #[test]
+    fn rejects_credentials_that_are_not_health_cards() {
+        let err = decode_jws(&jws(payload(&["VerifiableCredential"], serde_json::json!(1620847989)))).unwrap_err();
+        assert!(matches!(err, Error::ShcEncoding(_)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_nbf() {
+        let err = decode_jws(&jws(payload(&[HEALTH_CARD_TYPE], serde_json::json!(1e300)))).unwrap_err();
+        assert!(matches!(err, Error::Json(_)));
+    }
+
+    #[test]
+    fn assembles_chunks_out_of_order() {
+        let assembler = ShcChunkAssembler::new(std::time::Duration::from_secs(60));
+        let status = assembler.add_chunk(None, "shc:/3/3/5677").unwrap();
+        assert_eq!((status.received, status.total, status.complete), (1, 3, false));
+        assert!(matches!(assembler.take(&status.session), Err(Error::ShcSession(_))));
+
+        let session = status.session;
+        assembler.add_chunk(Some(&session), "shc:/1/3/0012").unwrap();
+        let status = assembler.add_chunk(Some(&session), "shc:/1/3/0012").unwrap();
+        assert_eq!((status.received, status.complete), (2, false));
+        let status = assembler.add_chunk(Some(&session), "shc:/2/3/34").unwrap();
+        assert_eq!((status.session.as_str(), status.received, status.complete), (session.as_str(), 3, true));
+
+        assert_eq!(assembler.take(&session).unwrap(), "shc:/0012345677");
+        assert!(matches!(assembler.take(&session), Err(Error::ShcSession(_))));
+    }
+
+    #[test]
+    fn rejects_chunks_of_a_different_card() {
+        let assembler = ShcChunkAssembler::new(std::time::Duration::from_secs(60));
+        let session = assembler.add_chunk(None, "shc:/1/2/00").unwrap().session;
+        let err = assembler.add_chunk(Some(&session), "shc:/2/3/00").unwrap_err();
+        assert!(matches!(err, Error::ShcEncoding(_)));
+
+        for chunk in ["shc:/0/2/00", "shc:/3/2/00", "shc:/1/21/00", "shc:/1/2/99", "shc:/1/2"] {
+            assert!(matches!(assembler.add_chunk(Some(&session), chunk), Err(Error::ShcEncoding(_))), "{}", chunk);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_and_expired_sessions() {
+        let assembler = ShcChunkAssembler::new(std::time::Duration::from_secs(60));
+        assert!(matches!(assembler.add_chunk(Some("unknown"), "shc:/1/2/00"), Err(Error::ShcSession(_))));
+        assert!(matches!(assembler.take("unknown"), Err(Error::ShcSession(_))));
+
+        let assembler = ShcChunkAssembler::new(std::time::Duration::ZERO);
+        let session = assembler.add_chunk(None, "shc:/1/2/00").unwrap().session;
+        assert!(matches!(assembler.add_chunk(Some(&session), "shc:/2/2/00"), Err(Error::ShcSession(_))));
+        assert!(assembler.sessions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn limits_sessions_in_progress() {
+        let assembler = ShcChunkAssembler::new(std::time::Duration::from_secs(60));
+        let session = assembler.add_chunk(None, "shc:/1/2/00").unwrap().session;
+        {
+            let mut sessions = assembler.sessions.lock().unwrap();
+            for i in 1..MAX_SHC_SESSIONS {
+                sessions.insert(i.to_string(), ShcChunkSession {
+                    created: std::time::Instant::now(),
+                    chunks: vec![None; 2],
+                });
+            }
+        }
+
+        assert!(matches!(assembler.add_chunk(None, "shc:/1/2/00"), Err(Error::ShcSession(_))));
+        assert!(assembler.add_chunk(Some(&session), "shc:/2/2/00").unwrap().complete);
+        assert_eq!(assembler.take(&session).unwrap(), "shc:/0000");
+        assert!(assembler.add_chunk(None, "shc:/1/2/00").is_ok());
+    }
+}
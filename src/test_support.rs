@@ -0,0 +1,246 @@
+use coset::TaggedCborSerializable;
+use serde_cbor::Value;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy)]
+pub enum KeyIdLocation {
+    Protected,
+    Unprotected,
+    None,
+}
+
+pub fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+pub fn map(entries: Vec<(Value, Value)>) -> Value {
+    Value::Map(entries.into_iter().collect())
+}
+
+pub fn with_field(map: Value, key: &str, value: Value) -> Value {
+    match map {
+        Value::Map(mut m) => {
+            m.insert(text(key), value);
+            Value::Map(m)
+        }
+        v => v
+    }
+}
+
+pub fn without_field(map: Value, key: &str) -> Value {
+    match map {
+        Value::Map(mut m) => {
+            m.remove(&text(key));
+            Value::Map(m)
+        }
+        v => v
+    }
+}
+
+pub fn name() -> Value {
+    map(vec![
+        (text("fn"), text("Musterfrau")),
+        (text("fnt"), text("MUSTERFRAU")),
+        (text("gn"), text("Erika")),
+        (text("gnt"), text("ERIKA")),
+    ])
+}
+
+/// An `eu_dgc_v1` vaccination certificate for dose `dose` of 2.
+pub fn vaccination(dose: i128) -> Value {
+    map(vec![
+        (text("ver"), text("1.3.0")),
+        (text("nam"), name()),
+        (text("dob"), text("1964-08-12")),
+        (text("v"), Value::Array(vec![map(vec![
+            (text("tg"), text("840539006")),
+            (text("vp"), text("1119349007")),
+            (text("mp"), text("EU/1/20/1528")),
+            (text("ma"), text("ORG-100030215")),
+            (text("dn"), Value::Integer(dose)),
+            (text("sd"), Value::Integer(2)),
+            (text("dt"), text("2021-05-29")),
+            (text("co"), text("DE")),
+            (text("is"), text("Robert Koch-Institut")),
+            (text("ci"), text(&format!("URN:UVCI:01DE/IZ12345A/5CWLU12RNOB9RXSEOP6FG8#{}", dose))),
+        ])])),
+    ])
+}
+
+/// An `eu_dgc_v1` test certificate for a sample taken at `sample_time`.
+pub fn test(sample_time: &str) -> Value {
+    map(vec![
+        (text("ver"), text("1.3.0")),
+        (text("nam"), name()),
+        (text("dob"), text("1964-08-12")),
+        (text("t"), Value::Array(vec![map(vec![
+            (text("tg"), text("840539006")),
+            (text("tt"), text("LP217198-3")),
+            (text("ma"), text("1232")),
+            (text("sc"), text(sample_time)),
+            (text("tr"), text("260415000")),
+            (text("tc"), text("Testzentrum Köln Hbf")),
+            (text("co"), text("DE")),
+            (text("is"), text("Robert Koch-Institut")),
+            (text("ci"), text(&format!("URN:UVCI:01DE/TZ12345A/{}", sample_time))),
+        ])])),
+    ])
+}
+
+/// CWT claims for an `eu_dgc_v1` certificate issued by `iss` at `iat`, expiring a year later.
+pub fn cwt(iss: &str, iat: i64, eu_dgc_v1: Value) -> Vec<u8> {
+    serde_cbor::to_vec(&map(vec![
+        (Value::Integer(1), text(iss)),
+        (Value::Integer(4), Value::Integer((iat + 365 * 24 * 60 * 60) as i128)),
+        (Value::Integer(6), Value::Integer(iat as i128)),
+        (Value::Integer(-260), map(vec![(Value::Integer(1), eu_dgc_v1)])),
+    ])).unwrap()
+}
+
+pub fn signing_key() -> openssl::pkey::PKey<openssl::pkey::Private> {
+    ec_signing_key(openssl::nid::Nid::X9_62_PRIME256V1)
+}
+
+pub fn ec_signing_key(curve: openssl::nid::Nid) -> openssl::pkey::PKey<openssl::pkey::Private> {
+    let group = openssl::ec::EcGroup::from_curve_name(curve).unwrap();
+    openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap()).unwrap()
+}
+
+/// Signs `data` with `alg` the way COSE expects, ECDSA signatures are the padded `r || s`.
+pub fn sign(alg: coset::iana::Algorithm, key: &openssl::pkey::PKey<openssl::pkey::Private>, data: &[u8]) -> Vec<u8> {
+    let ecdsa = |digest, component_len| {
+        let hash = openssl::hash::hash(digest, data).unwrap();
+        let sig = openssl::ecdsa::EcdsaSig::sign(&hash, key.ec_key().unwrap().as_ref()).unwrap();
+        let mut signature = sig.r().to_vec_padded(component_len).unwrap();
+        signature.extend(sig.s().to_vec_padded(component_len).unwrap());
+        signature
+    };
+    let rsa_pss = |digest| {
+        let mut signer = openssl::sign::Signer::new(digest, key).unwrap();
+        signer.set_rsa_padding(openssl::rsa::Padding::PKCS1_PSS).unwrap();
+        signer.set_rsa_pss_saltlen(openssl::sign::RsaPssSaltlen::DIGEST_LENGTH).unwrap();
+        signer.set_rsa_mgf1_md(digest).unwrap();
+        signer.update(data).unwrap();
+        signer.sign_to_vec().unwrap()
+    };
+
+    match alg {
+        coset::iana::Algorithm::ES256 => ecdsa(openssl::hash::MessageDigest::sha256(), 32),
+        coset::iana::Algorithm::ES384 => ecdsa(openssl::hash::MessageDigest::sha384(), 48),
+        coset::iana::Algorithm::ES512 => ecdsa(openssl::hash::MessageDigest::sha512(), 66),
+        coset::iana::Algorithm::PS256 => rsa_pss(openssl::hash::MessageDigest::sha256()),
+        coset::iana::Algorithm::PS384 => rsa_pss(openssl::hash::MessageDigest::sha384()),
+        coset::iana::Algorithm::PS512 => rsa_pss(openssl::hash::MessageDigest::sha512()),
+        coset::iana::Algorithm::EdDSA => openssl::sign::Signer::new_without_digest(key).unwrap()
+            .sign_oneshot_to_vec(data).unwrap(),
+        a => panic!("Unsupported test signing algorithm {:?}", a)
+    }
+}
+
+pub fn public_key(key: &openssl::pkey::PKey<openssl::pkey::Private>) -> openssl::pkey::PKey<openssl::pkey::Public> {
+    openssl::pkey::PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap()
+}
+
+/// Signs the CWT with ES256 and encodes it as an `HC1:` QR code payload.
+pub fn sign_hc1(
+    cwt: Vec<u8>, key: &openssl::pkey::PKey<openssl::pkey::Private>, kid: &[u8], kid_location: KeyIdLocation,
+) -> String {
+    let mut protected = coset::HeaderBuilder::new().algorithm(coset::iana::Algorithm::ES256);
+    let mut unprotected = coset::HeaderBuilder::new();
+    match kid_location {
+        KeyIdLocation::Protected => protected = protected.key_id(kid.to_vec()),
+        KeyIdLocation::Unprotected => unprotected = unprotected.key_id(kid.to_vec()),
+        KeyIdLocation::None => {}
+    }
+    let cose = coset::CoseSign1Builder::new()
+        .protected(protected.build())
+        .unprotected(unprotected.build())
+        .payload(cwt)
+        .create_signature(&[], |data| sign(coset::iana::Algorithm::ES256, key, data))
+        .build();
+
+    let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(&cose.to_tagged_vec().unwrap()).unwrap();
+    format!("HC1:{}", base45::encode_from_buffer(encoder.finish().unwrap()))
+}
+
+fn country_name(country: &str, cn: &str) -> openssl::x509::X509Name {
+    let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("C", country).unwrap();
+    name.append_entry_by_text("CN", cn).unwrap();
+    name.build()
+}
+
+/// A self-signed CSCA for `country`.
+pub fn csca(country: &str, key: &openssl::pkey::PKey<openssl::pkey::Private>) -> openssl::x509::X509 {
+    let name = country_name(country, "CSCA");
+    let mut cert = openssl::x509::X509Builder::new().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(key).unwrap();
+    cert.set_not_before(&openssl::asn1::Asn1Time::from_unix(0).unwrap()).unwrap();
+    cert.set_not_after(&openssl::asn1::Asn1Time::days_from_now(365).unwrap()).unwrap();
+    cert.append_extension(openssl::x509::extension::BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+    cert.append_extension(openssl::x509::extension::KeyUsage::new().critical().key_cert_sign().build().unwrap()).unwrap();
+    cert.sign(key, openssl::hash::MessageDigest::sha256()).unwrap();
+    cert.build()
+}
+
+/// A DSC for `country` issued by `csca`, valid between the `not_before` and `not_after` unix times and carrying the
+/// `extended_key_usage` OIDs, if any.
+pub fn dsc(
+    csca: &openssl::x509::X509, csca_key: &openssl::pkey::PKey<openssl::pkey::Private>, country: &str,
+    key: &openssl::pkey::PKey<openssl::pkey::Private>, not_before: i64, not_after: i64, extended_key_usage: &[&str],
+) -> openssl::x509::X509 {
+    let mut cert = openssl::x509::X509Builder::new().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&country_name(country, "DSC")).unwrap();
+    cert.set_issuer_name(csca.subject_name()).unwrap();
+    cert.set_pubkey(key).unwrap();
+    cert.set_not_before(&openssl::asn1::Asn1Time::from_unix(not_before).unwrap()).unwrap();
+    cert.set_not_after(&openssl::asn1::Asn1Time::from_unix(not_after).unwrap()).unwrap();
+    if !extended_key_usage.is_empty() {
+        let mut eku = openssl::x509::extension::ExtendedKeyUsage::new();
+        for oid in extended_key_usage {
+            eku.other(oid);
+        }
+        cert.append_extension(eku.build().unwrap()).unwrap();
+    }
+    cert.sign(csca_key, openssl::hash::MessageDigest::sha256()).unwrap();
+    cert.build()
+}
+
+/// Replaces `key` in the first entry of the `group` (`v`, `t`, `r` or `ex`) array of an `eu_dgc_v1` certificate.
+pub fn with_entry_field(eu_dgc_v1: Value, group: &str, key: &str, value: Value) -> Value {
+    let entry = match &eu_dgc_v1 {
+        Value::Map(m) => match m.get(&text(group)) {
+            Some(Value::Array(entries)) => entries[0].clone(),
+            _ => return eu_dgc_v1
+        },
+        _ => return eu_dgc_v1
+    };
+    with_field(eu_dgc_v1, group, Value::Array(vec![with_field(entry, key, value)]))
+}
+
+/// Pass type signing keys with a self-signed certificate.
+pub fn pass_signing_keys() -> crate::pkpass::PKPassSigningKeys {
+    let private_key = openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", crate::pkpass::PASS_TYPE_ID).unwrap();
+    let name = name.build();
+    let mut cert = openssl::x509::X509Builder::new().unwrap();
+    cert.set_version(2).unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&private_key).unwrap();
+    cert.set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap()).unwrap();
+    cert.sign(&private_key, openssl::hash::MessageDigest::sha256()).unwrap();
+
+    crate::pkpass::PKPassSigningKeys {
+        public_cert: cert.build(),
+        private_key,
+        intermediate_certs: openssl::stack::Stack::new().unwrap(),
+    }
+}
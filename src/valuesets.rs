@@ -0,0 +1,309 @@
+use crate::error::ValueSetError;
+
+const VALUE_SET_COUNTRY_CODE_STR: &str = include_str!("../eu-dcc-valuesets/country-2-codes.json");
+const VALUE_SET_DISEASE_STR: &str = include_str!("../eu-dcc-valuesets/disease-agent-targeted.json");
+const VALUE_SET_TEST_MANUFACTURER_STR: &str = include_str!("../eu-dcc-valuesets/test-manf.json");
+const VALUE_SET_TEST_RESULT_STR: &str = include_str!("../eu-dcc-valuesets/test-result.json");
+const VALUE_SET_TEST_TYPE_STR: &str = include_str!("../eu-dcc-valuesets/test-type.json");
+const VALUE_SET_VACCINE_MANUFACTURER_STR: &str = include_str!("../eu-dcc-valuesets/vaccine-mah-manf.json");
+const VALUE_SET_VACCINE_PRODUCT_STR: &str = include_str!("../eu-dcc-valuesets/vaccine-medicinal-product.json");
+const VALUE_SET_VACCINE_PROPHYLAXIS_STR: &str = include_str!("../eu-dcc-valuesets/vaccine-prophylaxis.json");
+
+#[derive(Debug, Deserialize)]
+pub struct ValueSet {
+    #[serde(rename = "valueSetId")]
+    pub id: String,
+    #[serde(rename = "valueSetDate")]
+    pub date: String,
+    #[serde(rename = "valueSetValues")]
+    pub values: std::collections::HashMap<String, ValueSetValue>,
+    #[serde(skip)]
+    pub source: Option<std::path::PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ValueSetValue {
+    pub display: String,
+    pub lang: String,
+    pub active: bool,
+    pub version: String,
+    pub system: String,
+    #[serde(skip)]
+    pub unrecognised_code: Option<String>,
+    /// Display names in other languages by language code, from `<value_set_dir>/<lang>/`
+    #[serde(skip)]
+    pub translations: std::collections::HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct ValueSets {
+    pub country_code: ValueSet,
+    pub disease: ValueSet,
+    pub test_manufacturer: ValueSet,
+    pub test_result: ValueSet,
+    pub test_type: ValueSet,
+    pub vaccine_manufacturer: ValueSet,
+    pub vaccine_product: ValueSet,
+    pub vaccine_prophylaxis: ValueSet,
+}
+
+static UNKNOWN_VALUE_SET_CODES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+pub fn unknown_value_set_code_count() -> usize {
+    UNKNOWN_VALUE_SET_CODES.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+lazy_static! {
+    static ref VALUE_SETS: std::sync::RwLock<std::sync::Arc<ValueSets>> =
+        std::sync::RwLock::new(std::sync::Arc::new(ValueSets::compiled_in()));
+}
+
+pub fn current() -> std::sync::Arc<ValueSets> {
+    VALUE_SETS.read().unwrap().clone()
+}
+
+pub fn install(value_sets: ValueSets) {
+    *VALUE_SETS.write().unwrap() = std::sync::Arc::new(value_sets);
+}
+
+impl ValueSets {
+    pub fn compiled_in() -> Self {
+        ValueSets {
+            country_code: serde_json::from_str(VALUE_SET_COUNTRY_CODE_STR).unwrap(),
+            disease: serde_json::from_str(VALUE_SET_DISEASE_STR).unwrap(),
+            test_manufacturer: serde_json::from_str(VALUE_SET_TEST_MANUFACTURER_STR).unwrap(),
+            test_result: serde_json::from_str(VALUE_SET_TEST_RESULT_STR).unwrap(),
+            test_type: serde_json::from_str(VALUE_SET_TEST_TYPE_STR).unwrap(),
+            vaccine_manufacturer: serde_json::from_str(VALUE_SET_VACCINE_MANUFACTURER_STR).unwrap(),
+            vaccine_product: serde_json::from_str(VALUE_SET_VACCINE_PRODUCT_STR).unwrap(),
+            vaccine_prophylaxis: serde_json::from_str(VALUE_SET_VACCINE_PROPHYLAXIS_STR).unwrap(),
+        }
+    }
+
+    pub async fn load_from_dir(dir: &str) -> Result<Self, ValueSetError> {
+        let compiled_in = ValueSets::compiled_in();
+        let dir = std::path::Path::new(dir);
+        Ok(ValueSets {
+            country_code: load_value_set(dir, "country-2-codes.json", compiled_in.country_code).await?,
+            disease: load_value_set(dir, "disease-agent-targeted.json", compiled_in.disease).await?,
+            test_manufacturer: load_value_set(dir, "test-manf.json", compiled_in.test_manufacturer).await?,
+            test_result: load_value_set(dir, "test-result.json", compiled_in.test_result).await?,
+            test_type: load_value_set(dir, "test-type.json", compiled_in.test_type).await?,
+            vaccine_manufacturer: load_value_set(dir, "vaccine-mah-manf.json", compiled_in.vaccine_manufacturer).await?,
+            vaccine_product: load_value_set(dir, "vaccine-medicinal-product.json", compiled_in.vaccine_product).await?,
+            vaccine_prophylaxis: load_value_set(dir, "vaccine-prophylaxis.json", compiled_in.vaccine_prophylaxis).await?,
+        })
+    }
+
+    pub fn all(&self) -> [&ValueSet; 8] {
+        [
+            &self.country_code, &self.disease, &self.test_manufacturer, &self.test_result, &self.test_type,
+            &self.vaccine_manufacturer, &self.vaccine_product, &self.vaccine_prophylaxis,
+        ]
+    }
+}
+
+async fn load_value_set(
+    dir: &std::path::Path, file_name: &str, compiled_in: ValueSet,
+) -> Result<ValueSet, ValueSetError> {
+    let path = dir.join(file_name);
+    let mut value_set = match tokio::fs::read(&path).await {
+        Ok(value_set_bytes) => {
+            let mut value_set: ValueSet = serde_json::from_slice(&value_set_bytes)
+                .map_err(|e| ValueSetError::Json(format!("Unable to decode value set {}", path.display()), e))?;
+            if value_set.id != compiled_in.id {
+                return Err(ValueSetError::IdMismatch(format!(
+                    "Value set {} has valueSetId {}, expected {}", path.display(), value_set.id, compiled_in.id
+                )));
+            }
+            info!("Loaded value set {} from {} ({})", value_set.id, path.display(), value_set.date);
+            value_set.source = Some(path);
+            value_set
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("No {} in value set directory, using compiled in {} from {}", file_name, compiled_in.id, compiled_in.date);
+            compiled_in
+        }
+        Err(e) => return Err(ValueSetError::Io(format!("Unable to read value set {}", path.display()), e))
+    };
+
+    for lang in &crate::localisation::PASS_LANGUAGES[1..] {
+        let path = dir.join(lang).join(file_name);
+        let value_set_bytes = match tokio::fs::read(&path).await {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(ValueSetError::Io(format!("Unable to read value set {}", path.display()), e))
+        };
+        let translated: ValueSet = serde_json::from_slice(&value_set_bytes)
+            .map_err(|e| ValueSetError::Json(format!("Unable to decode value set {}", path.display()), e))?;
+        if translated.id != value_set.id {
+            return Err(ValueSetError::IdMismatch(format!(
+                "Value set {} has valueSetId {}, expected {}", path.display(), translated.id, value_set.id
+            )));
+        }
+        for (code, translated_value) in translated.values {
+            if let Some(value) = value_set.values.get_mut(&code) {
+                value.translations.insert(lang.to_string(), translated_value.display);
+            }
+        }
+        info!("Loaded {} translations of value set {} from {}", lang, value_set.id, path.display());
+    }
+
+    Ok(value_set)
+}
+
+pub(crate) fn de_value_set_disease<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().disease, "disease", s))
+}
+
+pub(crate) fn de_value_set_vaccine<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().vaccine_prophylaxis, "vaccine", s))
+}
+
+pub(crate) fn de_value_set_vaccine_product<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().vaccine_product, "vaccine product", s))
+}
+
+pub(crate) fn de_value_set_vaccine_manufacturer<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().vaccine_manufacturer, "vaccine manufacturer", s))
+}
+
+pub(crate) fn de_value_set_test_type<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().test_type, "test type", s))
+}
+
+pub(crate) fn de_value_set_test_result<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().test_result, "test result", s))
+}
+
+pub(crate) fn de_value_set_country<'de, D: serde::Deserializer<'de>>(d: D) -> Result<ValueSetValue, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).map(|s| lookup_value_set(&current().country_code, "country", s))
+}
+
+
+fn lookup_value_set(value_set: &ValueSet, value_set_name: &str, code: String) -> ValueSetValue {
+    match value_set.values.get(&code) {
+        Some(v) => v.clone(),
+        None => {
+            let count = UNKNOWN_VALUE_SET_CODES.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            warn!(
+                "Unknown {} value set code {} in {} ({} unknown codes seen), value sets may need updating",
+                value_set_name, code, value_set.id, count
+            );
+            ValueSetValue {
+                display: format!("{} (unrecognised)", code),
+                lang: "en".to_string(),
+                active: false,
+                version: String::new(),
+                system: String::new(),
+                unrecognised_code: Some(code),
+                translations: std::collections::HashMap::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, KeyIdLocation};
+
+    #[test]
+    fn renders_unknown_codes_as_unrecognised() {
+        let value_sets = ValueSets::compiled_in();
+        let known = lookup_value_set(&value_sets.vaccine_product, "vaccine product", "EU/1/20/1528".to_string());
+        assert_eq!(known.display, "Comirnaty");
+        assert_eq!(known.unrecognised_code, None);
+
+        let before = unknown_value_set_code_count();
+        let unknown = lookup_value_set(&value_sets.vaccine_product, "vaccine product", "EU/1/99/0000".to_string());
+        assert_eq!(unknown.display, "EU/1/99/0000 (unrecognised)");
+        assert_eq!(unknown.unrecognised_code.as_deref(), Some("EU/1/99/0000"));
+        assert!(!unknown.active);
+        assert!(unknown_value_set_code_count() > before);
+    }
+
+    fn value_set(id: &str, code: &str, display: &str) -> String {
+        serde_json::json!({
+            "valueSetId": id,
+            "valueSetDate": "2022-01-01",
+            "valueSetValues": {
+                code: {
+                    "display": display,
+                    "lang": "en",
+                    "active": true,
+                    "version": "",
+                    "system": "https://ec.europa.eu/health/documents/community-register/html/",
+                },
+            },
+        }).to_string()
+    }
+
+    #[rocket::async_test]
+    async fn loads_value_sets_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let product_id = ValueSets::compiled_in().vaccine_product.id;
+        std::fs::write(
+            dir.path().join("vaccine-medicinal-product.json"), value_set(&product_id, "EU/1/22/0001", "Updated vaccine"),
+        ).unwrap();
+        std::fs::create_dir(dir.path().join("de")).unwrap();
+        std::fs::write(
+            dir.path().join("de").join("vaccine-medicinal-product.json"),
+            value_set(&product_id, "EU/1/22/0001", "Aktualisierter Impfstoff"),
+        ).unwrap();
+
+        let value_sets = ValueSets::load_from_dir(dir.path().to_str().unwrap()).await.unwrap();
+        let product = &value_sets.vaccine_product;
+        assert_eq!(product.source.as_deref(), Some(dir.path().join("vaccine-medicinal-product.json").as_path()));
+        assert_eq!(product.date, "2022-01-01");
+        assert_eq!(product.values["EU/1/22/0001"].display, "Updated vaccine");
+        assert_eq!(product.values["EU/1/22/0001"].translations["de"], "Aktualisierter Impfstoff");
+        assert!(!product.values.contains_key("EU/1/20/1528"));
+        assert_eq!(value_sets.country_code.source, None);
+        assert_eq!(value_sets.country_code.values.len(), ValueSets::compiled_in().country_code.values.len());
+    }
+
+    #[rocket::async_test]
+    async fn rejects_value_sets_with_another_id() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("test-type.json"), value_set("covid-19-lab-result", "1", "Test")).unwrap();
+        let err = ValueSets::load_from_dir(dir.path().to_str().unwrap()).await.unwrap_err();
+        assert!(matches!(err, ValueSetError::IdMismatch(_)));
+
+        let dir = tempfile::tempdir().unwrap();
+        let test_type_id = ValueSets::compiled_in().test_type.id;
+        std::fs::write(dir.path().join("test-type.json"), value_set(&test_type_id, "1", "Test")).unwrap();
+        std::fs::create_dir(dir.path().join("de")).unwrap();
+        std::fs::write(dir.path().join("de").join("test-type.json"), value_set("covid-19-lab-result", "1", "Test")).unwrap();
+        let err = ValueSets::load_from_dir(dir.path().to_str().unwrap()).await.unwrap_err();
+        assert!(matches!(err, ValueSetError::IdMismatch(_)));
+
+        std::fs::write(dir.path().join("test-type.json"), "not json").unwrap();
+        let err = ValueSets::load_from_dir(dir.path().to_str().unwrap()).await.unwrap_err();
+        assert!(matches!(err, ValueSetError::Json(_, _)));
+    }
+
+    #[test]
+    fn decodes_certificates_with_unknown_codes() {
+        let eu_dgc_v1 = test_support::with_entry_field(
+            test_support::vaccination(2), "v", "mp", test_support::text("EU/1/99/0000"),
+        );
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", 1622316073, eu_dgc_v1), &test_support::signing_key(), b"kid", KeyIdLocation::Protected,
+        );
+        let cert = crate::decode::decode_hc1(&data, crate::decode::DecodeMode::Strict).unwrap();
+        let vaccination = match &cert.payload.hcert.eu_dgc_v1.group {
+            crate::dcc::EUDigitalGreenCertGroup::Vaccination(v) => &v[0],
+            g => panic!("Expected a vaccination, got {:?}", g),
+        };
+        assert_eq!(vaccination.product.display, "EU/1/99/0000 (unrecognised)");
+        assert_eq!(vaccination.manufacturer.unrecognised_code, None);
+    }
+}
@@ -0,0 +1,1300 @@
+use chrono::prelude::*;
+use std::fmt::Formatter;
+use crate::dcc::{EHealthPayload, EUDigitalGreenCertGroup};
+use crate::decode::HC1Certificate;
+use crate::error::{Error, SignatureError, TrustListError};
+use crate::nzcp::NZCPCertificate;
+use crate::shc::SmartHealthCard;
+
+const DSC_EKU_TEST: [&str; 2] = ["1.3.6.1.4.1.1847.2021.1.1", "1.3.6.1.4.1.0.1847.2021.1.1"];
+const DSC_EKU_VACCINATION: [&str; 2] = ["1.3.6.1.4.1.1847.2021.1.2", "1.3.6.1.4.1.0.1847.2021.1.2"];
+const DSC_EKU_RECOVERY: [&str; 2] = ["1.3.6.1.4.1.1847.2021.1.3", "1.3.6.1.4.1.0.1847.2021.1.3"];
+
+#[derive(Debug, Deserialize)]
+struct UKSigningCert {
+    #[serde(deserialize_with = "de_base64")]
+    kid: Vec<u8>,
+    #[serde(rename = "publicKey", deserialize_with = "de_base64_ec_key")]
+    public_key: openssl::ec::EcKey<openssl::pkey::Public>,
+}
+
+fn de_base64<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d).and_then(|s|
+        base64::decode(&s).map_err(serde::de::Error::custom)
+    )
+}
+
+fn de_base64_ec_key<'de, D: serde::Deserializer<'de>>(d: D) -> Result<openssl::ec::EcKey<openssl::pkey::Public>, D::Error> {
+    use serde::de::Deserialize;
+    String::deserialize(d)
+        .and_then(|s| base64::decode(&s).map_err(serde::de::Error::custom))
+        .and_then(|c| openssl::ec::EcKey::public_key_from_der(&c).map_err(serde::de::Error::custom))
+}
+
+#[derive(Debug, Deserialize)]
+struct VCIIssuerList {
+    participating_issuers: Vec<VCIIssuer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VCIIssuer {
+    iss: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DIDDocument {
+    id: String,
+    #[serde(rename = "verificationMethod", default)]
+    verification_method: Vec<DIDVerificationMethod>,
+    #[serde(rename = "assertionMethod", default)]
+    assertion_method: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DIDVerificationMethod {
+    id: String,
+    #[serde(rename = "type")]
+    method_type: String,
+    #[serde(rename = "publicKeyJwk", default)]
+    public_key_jwk: Option<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DCCTrustListEntry {
+    #[serde(deserialize_with = "de_base64")]
+    kid: Vec<u8>,
+    country: String,
+    #[serde(rename = "certificateType", default)]
+    certificate_type: Option<String>,
+    #[serde(rename = "rawData", deserialize_with = "de_base64")]
+    raw_data: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevocationBatch {
+    #[serde(deserialize_with = "de_base64")]
+    kid: Vec<u8>,
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+    #[serde(rename = "hashType")]
+    hash_type: RevocationHashType,
+    entries: Vec<RevocationBatchEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevocationBatchEntry {
+    #[serde(deserialize_with = "de_base64")]
+    hash: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum RevocationHashType {
+    #[serde(rename = "SIGNATURE")]
+    Signature,
+    #[serde(rename = "UCI")]
+    Uci,
+    #[serde(rename = "COUNTRYCODEUCI")]
+    CountryCodeUci,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+struct RevocationKey {
+    kid: Vec<u8>,
+    hash_type: RevocationHashType,
+}
+
+#[derive(Debug, Clone)]
+pub enum TrustListSource {
+    DCCGateway(String),
+    CSCAList(String),
+    UKFile(String),
+    UKUrl(String),
+    RevocationDir(String),
+    SHCIssuerDir(String),
+    DIDDocumentDir(String),
+}
+
+impl std::fmt::Display for TrustListSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustListSource::DCCGateway(p) => write!(f, "DCC trust list {}", p),
+            TrustListSource::CSCAList(p) => write!(f, "CSCA trust list {}", p),
+            TrustListSource::UKFile(p) => write!(f, "UK trust list {}", p),
+            TrustListSource::UKUrl(u) => write!(f, "UK trust list {}", u),
+            TrustListSource::RevocationDir(p) => write!(f, "revocation lists {}", p),
+            TrustListSource::SHCIssuerDir(p) => write!(f, "SMART Health Card issuers {}", p),
+            TrustListSource::DIDDocumentDir(p) => write!(f, "DID documents {}", p),
+        }
+    }
+}
+
+impl TrustListSource {
+    pub async fn load(&self) -> Result<PassSigningCerts, TrustListError> {
+        match self {
+            TrustListSource::DCCGateway(path) | TrustListSource::CSCAList(path) => {
+                let trust_list_bytes = tokio::fs::read(path).await
+                    .map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))?;
+                let trust_list: Vec<DCCTrustListEntry> = serde_json::from_slice(&trust_list_bytes)
+                    .map_err(|e| TrustListError::Json(format!("Unable to decode {}", self), e))?;
+                Ok(dcc_trust_list_to_certs(trust_list, matches!(self, TrustListSource::CSCAList(_))))
+            }
+            TrustListSource::UKFile(path) => {
+                let trust_list_bytes = tokio::fs::read(path).await
+                    .map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))?;
+                let trust_list: Vec<UKSigningCert> = serde_json::from_slice(&trust_list_bytes)
+                    .map_err(|e| TrustListError::Json(format!("Unable to decode {}", self), e))?;
+                uk_trust_list_to_certs(trust_list)
+            }
+            TrustListSource::UKUrl(url) => {
+                let trust_list: Vec<UKSigningCert> = reqwest::get(url).await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| TrustListError::Http(format!("Unable to download {}", self), e))?
+                    .json().await
+                    .map_err(|e| TrustListError::Http(format!("Unable to decode {}", self), e))?;
+                uk_trust_list_to_certs(trust_list)
+            }
+            TrustListSource::RevocationDir(path) => {
+                let mut certs = PassSigningCerts::default();
+                let mut dir = tokio::fs::read_dir(path).await
+                    .map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))?;
+                while let Some(file) = dir.next_entry().await.map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))? {
+                    let file_path = file.path();
+                    if file_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                        continue;
+                    }
+                    let batch_bytes = tokio::fs::read(&file_path).await
+                        .map_err(|e| TrustListError::Io(format!("Unable to read revocation list {}", file_path.display()), e))?;
+                    let batch: RevocationBatch = serde_json::from_slice(&batch_bytes)
+                        .map_err(|e| TrustListError::Json(format!("Unable to decode revocation list {}", file_path.display()), e))?;
+                    if let Some(expires) = batch.expires {
+                        if expires < Utc::now() {
+                            continue;
+                        }
+                    }
+                    certs.revocations.entry(RevocationKey {
+                        kid: batch.kid,
+                        hash_type: batch.hash_type,
+                    }).or_insert_with(std::collections::HashSet::new).extend(batch.entries.into_iter().map(|e| e.hash));
+                }
+                Ok(certs)
+            }
+            TrustListSource::SHCIssuerDir(path) => {
+                let mut certs = PassSigningCerts::default();
+                let dir = std::path::Path::new(path);
+                let issuers_bytes = tokio::fs::read(dir.join("vci-issuers.json")).await
+                    .map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))?;
+                let issuers: VCIIssuerList = serde_json::from_slice(&issuers_bytes)
+                    .map_err(|e| TrustListError::Json(format!("Unable to decode {}", self), e))?;
+                for issuer in issuers.participating_issuers {
+                    let iss = issuer.iss.trim_end_matches('/');
+                    let jwks_path = match iss.split_once("://") {
+                        Some((_, p)) => dir.join(p).join(".well-known").join("jwks.json"),
+                        None => {
+                            warn!("Skipping invalid SMART Health Card issuer {}", iss);
+                            continue;
+                        }
+                    };
+                    let jwks_bytes = match tokio::fs::read(&jwks_path).await {
+                        Ok(b) => b,
+                        Err(e) => {
+                            warn!("Skipping SMART Health Card issuer {}: unable to read {}: {}", iss, jwks_path.display(), e);
+                            continue;
+                        }
+                    };
+                    let jwks: Jwks = match serde_json::from_slice(&jwks_bytes) {
+                        Ok(j) => j,
+                        Err(e) => {
+                            warn!("Skipping SMART Health Card issuer {}: unable to decode {}: {}", iss, jwks_path.display(), e);
+                            continue;
+                        }
+                    };
+                    for key in jwks.keys {
+                        let kid = match &key.kid {
+                            Some(k) => k.clone(),
+                            None => continue
+                        };
+                        let pkey = match jwk_to_pkey(&key) {
+                            Ok(k) => k,
+                            Err(e) => {
+                                warn!("Skipping invalid SMART Health Card key {} for {}: {}", kid, iss, e);
+                                continue;
+                            }
+                        };
+                        certs.shc_keys.insert(PassSigningCertKey {
+                            iss: iss.to_string(),
+                            kid: kid.into_bytes(),
+                        }, PassSigningCert {
+                            pkey,
+                            cert: None,
+                            key_usage: None,
+                        });
+                    }
+                }
+                Ok(certs)
+            }
+            TrustListSource::DIDDocumentDir(path) => {
+                let mut certs = PassSigningCerts::default();
+                let mut dir = tokio::fs::read_dir(path).await
+                    .map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))?;
+                while let Some(file) = dir.next_entry().await.map_err(|e| TrustListError::Io(format!("Unable to read {}", self), e))? {
+                    let file_path = file.path();
+                    if file_path.extension().and_then(|e| e.to_str()) != Some("json") {
+                        continue;
+                    }
+                    let doc_bytes = tokio::fs::read(&file_path).await
+                        .map_err(|e| TrustListError::Io(format!("Unable to read DID document {}", file_path.display()), e))?;
+                    let doc: DIDDocument = serde_json::from_slice(&doc_bytes)
+                        .map_err(|e| TrustListError::Json(format!("Unable to decode DID document {}", file_path.display()), e))?;
+                    for method in doc.verification_method {
+                        if method.method_type != "JsonWebKey2020" || !doc.assertion_method.contains(&method.id) {
+                            continue;
+                        }
+                        let kid = match method.id.strip_prefix(&format!("{}#", doc.id)) {
+                            Some(k) => k.to_string(),
+                            None => continue
+                        };
+                        let pkey = match method.public_key_jwk.as_ref().ok_or_else(|| "no publicKeyJwk".to_string())
+                            .and_then(jwk_to_pkey) {
+                            Ok(k) => k,
+                            Err(e) => {
+                                warn!("Skipping invalid DID key {}: {}", method.id, e);
+                                continue;
+                            }
+                        };
+                        certs.did_keys.insert(PassSigningCertKey {
+                            iss: doc.id.clone(),
+                            kid: kid.into_bytes(),
+                        }, PassSigningCert {
+                            pkey,
+                            cert: None,
+                            key_usage: None,
+                        });
+                    }
+                }
+                Ok(certs)
+            }
+        }
+    }
+}
+
+fn jwk_to_pkey(key: &Jwk) -> Result<openssl::pkey::PKey<openssl::pkey::Public>, String> {
+    if key.kty != "EC" || key.crv.as_deref() != Some("P-256") {
+        return Err(format!("unsupported key type {} {}", key.kty, key.crv.as_deref().unwrap_or_default()));
+    }
+    let coordinate = |c: &Option<String>| -> Result<openssl::bn::BigNum, String> {
+        let bytes = base64::decode_config(c.as_deref().ok_or("missing EC coordinate")?, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| e.to_string())?;
+        openssl::bn::BigNum::from_slice(&bytes).map_err(|e| e.to_string())
+    };
+    let group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)
+        .map_err(|e| e.to_string())?;
+    let x = coordinate(&key.x)?;
+    let y = coordinate(&key.y)?;
+    let ec_key = openssl::ec::EcKey::from_public_key_affine_coordinates(&group, &x, &y)
+        .map_err(|e| e.to_string())?;
+    ec_key.check_key().map_err(|e| e.to_string())?;
+    openssl::pkey::PKey::from_ec_key(ec_key).map_err(|e| e.to_string())
+}
+
+fn dcc_trust_list_to_certs(trust_list: Vec<DCCTrustListEntry>, all_csca: bool) -> PassSigningCerts {
+    let mut certs = PassSigningCerts::default();
+    for entry in trust_list {
+        let is_csca = all_csca || entry.certificate_type.as_deref() == Some("CSCA");
+        if !is_csca {
+            if let Some(cert_type) = &entry.certificate_type {
+                if cert_type != "DSC" {
+                    continue;
+                }
+            }
+        }
+
+        let cert = match openssl::x509::X509::from_der(&entry.raw_data) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Skipping invalid certificate {} for {}: {}", base64::encode(&entry.kid), entry.country, e);
+                continue;
+            }
+        };
+
+        if is_csca {
+            certs.cscas.entry(entry.country).or_insert_with(Vec::new).push(cert);
+            continue;
+        }
+
+        let pkey = match cert.public_key() {
+            Ok(k) => k,
+            Err(e) => {
+                warn!("Skipping invalid DSC {} for {}: {}", base64::encode(&entry.kid), entry.country, e);
+                continue;
+            }
+        };
+
+        let key_usage = match dsc_key_usage(&entry.raw_data) {
+            Ok(u) => u,
+            Err(e) => {
+                warn!("Skipping invalid DSC {} for {}: {}", base64::encode(&entry.kid), entry.country, e);
+                continue;
+            }
+        };
+
+        certs.dscs.insert(PassSigningCertKey {
+            iss: entry.country,
+            kid: entry.kid,
+        }, PassSigningCert {
+            pkey,
+            cert: Some(cert),
+            key_usage,
+        });
+    }
+    certs
+}
+
+fn uk_trust_list_to_certs(trust_list: Vec<UKSigningCert>) -> Result<PassSigningCerts, TrustListError> {
+    let mut certs = PassSigningCerts::default();
+    for cert in trust_list {
+        certs.dscs.insert(PassSigningCertKey {
+            iss: "GB".to_string(),
+            kid: cert.kid,
+        }, PassSigningCert {
+            pkey: openssl::pkey::PKey::from_ec_key(cert.public_key)
+                .map_err(|e| TrustListError::Crypto("Invalid UK signing key".to_string(), e))?,
+            cert: None,
+            key_usage: None,
+        });
+    }
+    Ok(certs)
+}
+
+fn dsc_key_usage(cert: &[u8]) -> Result<Option<Vec<DSCKeyUsage>>, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert)
+        .map_err(|e| format!("Unable to parse certificate: {}", e))?;
+    let eku = match cert.extended_key_usage().map_err(|e| format!("Invalid extended key usage: {}", e))? {
+        Some(e) => e.value,
+        None => return Ok(None)
+    };
+
+    let mut key_usage = vec![];
+    for oid in &eku.other {
+        let oid = oid.to_id_string();
+        if DSC_EKU_TEST.contains(&oid.as_str()) {
+            key_usage.push(DSCKeyUsage::Test);
+        } else if DSC_EKU_VACCINATION.contains(&oid.as_str()) {
+            key_usage.push(DSCKeyUsage::Vaccination);
+        } else if DSC_EKU_RECOVERY.contains(&oid.as_str()) {
+            key_usage.push(DSCKeyUsage::Recovery);
+        }
+    }
+
+    if key_usage.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(key_usage))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PassSigningCerts {
+    dscs: std::collections::HashMap<PassSigningCertKey, PassSigningCert>,
+    cscas: std::collections::HashMap<String, Vec<openssl::x509::X509>>,
+    revocations: std::collections::HashMap<RevocationKey, std::collections::HashSet<Vec<u8>>>,
+    shc_keys: std::collections::HashMap<PassSigningCertKey, PassSigningCert>,
+    did_keys: std::collections::HashMap<PassSigningCertKey, PassSigningCert>,
+}
+
+impl PassSigningCerts {
+    pub fn has_country(&self, iss: &str) -> bool {
+        self.dscs.keys().any(|k| k.iss == iss)
+    }
+
+    pub fn candidates(&self, iss: &str, kid: &[u8]) -> Vec<&PassSigningCert> {
+        if kid.is_empty() {
+            self.dscs.iter()
+                .filter(|(k, _)| k.iss == iss)
+                .map(|(_, c)| c)
+                .collect()
+        } else {
+            self.dscs.get(&PassSigningCertKey {
+                iss: iss.to_string(),
+                kid: kid.to_vec(),
+            }).into_iter().collect()
+        }
+    }
+
+    pub fn signing_key_count(&self) -> usize {
+        self.dscs.len() + self.shc_keys.len() + self.did_keys.len()
+    }
+
+    pub fn verify_hc1(&self, hc1: &HC1Certificate) -> Result<Verification, Error> {
+        let payload = &hc1.payload;
+        let mut verification = Verification::Unverified;
+
+        if self.has_country(&payload.iss) {
+            let candidates = self.candidates(&payload.iss, cose_key_id(&hc1.cose));
+            if candidates.is_empty() {
+                return Err(Error::UnknownSigningKey);
+            }
+
+            let mut failures = vec![];
+            let signing_cert = match candidates.into_iter().find(|c| match verify_cose_signature(&hc1.cose, c) {
+                Ok(_) => true,
+                Err(e) => {
+                    failures.push(e);
+                    false
+                }
+            }) {
+                Some(c) => c,
+                None => return Err(Error::Signature(SignatureError::NoKeyMatched(failures)))
+            };
+
+            signing_cert.check_valid_for(&payload.iss, payload.iat)?;
+            signing_cert.check_key_usage(&payload.hcert.eu_dgc_v1.group)?;
+            verification = Verification::Verified;
+        }
+
+        let revocation_hashes = revocation_hashes(&hc1.cose, payload).map_err(Error::RevocationCheck)?;
+        let kid = cose_key_id(&hc1.cose);
+        if revocation_hashes.iter().any(|(t, h)| self.is_revoked(kid, *t, h)) {
+            return Err(Error::Revoked);
+        }
+
+        Ok(verification)
+    }
+
+    pub fn has_shc_issuer(&self, iss: &str) -> bool {
+        self.shc_keys.keys().any(|k| k.iss == iss)
+    }
+
+    pub fn shc_candidates(&self, iss: &str, kid: Option<&str>) -> Vec<&PassSigningCert> {
+        match kid {
+            Some(kid) => self.shc_keys.get(&PassSigningCertKey {
+                iss: iss.to_string(),
+                kid: kid.as_bytes().to_vec(),
+            }).into_iter().collect(),
+            None => self.shc_keys.iter()
+                .filter(|(k, _)| k.iss == iss)
+                .map(|(_, c)| c)
+                .collect()
+        }
+    }
+
+    pub fn verify_shc(&self, card: &SmartHealthCard) -> Result<Verification, Error> {
+        let iss = card.payload.iss.trim_end_matches('/');
+        if !self.has_shc_issuer(iss) {
+            return Ok(Verification::Unverified);
+        }
+
+        let candidates = self.shc_candidates(iss, card.header.kid.as_deref());
+        if candidates.is_empty() {
+            return Err(Error::UnknownSigningKey);
+        }
+        if card.header.alg != "ES256" {
+            return Err(Error::Signature(SignatureError::UnsupportedAlgorithm(card.header.alg.clone())));
+        }
+
+        let mut failures = vec![];
+        for candidate in candidates {
+            match verify_ecdsa_signature(
+                openssl::hash::MessageDigest::sha256(), 32, &candidate.pkey,
+                &card.signature, card.signing_input.as_bytes(),
+            ) {
+                Ok(true) => return Ok(Verification::Verified),
+                Ok(false) => failures.push(SignatureError::Mismatch),
+                Err(e) => failures.push(e),
+            }
+        }
+        Err(Error::Signature(SignatureError::NoKeyMatched(failures)))
+    }
+
+    pub fn has_did(&self, iss: &str) -> bool {
+        self.did_keys.keys().any(|k| k.iss == iss)
+    }
+
+    /// NZ COVID Passes are only accepted from an issuer with a loaded DID document, as the specification requires
+    /// verifiers to reject passes from any other `iss`.
+    pub fn verify_nzcp(&self, nzcp: &NZCPCertificate) -> Result<Verification, Error> {
+        let iss = &nzcp.payload.iss;
+        if !self.has_did(iss) {
+            return Err(Error::UnknownSigningKey);
+        }
+
+        if nzcp.cose.protected.alg != Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES256)) {
+            return Err(Error::Signature(SignatureError::UnsupportedAlgorithm(
+                format!("{:?}, NZ COVID Passes must be signed with ES256", nzcp.cose.protected.alg)
+            )));
+        }
+        let signing_key = self.did_keys.get(&PassSigningCertKey {
+            iss: iss.to_string(),
+            kid: nzcp.cose.protected.key_id.clone(),
+        }).ok_or(Error::UnknownSigningKey)?;
+
+        // Signed over the protected header as received, NZ passes put the kid before the alg unlike `coset`
+        let sig_structure = serde_cbor::to_vec(&serde_cbor::Value::Array(vec![
+            serde_cbor::Value::Text("Signature1".to_string()),
+            serde_cbor::Value::Bytes(nzcp.protected_header.clone()),
+            serde_cbor::Value::Bytes(vec![]),
+            serde_cbor::Value::Bytes(nzcp.cose.payload.clone().unwrap_or_default()),
+        ])).map_err(Error::Cbor)?;
+        verify_signature(
+            &coset::Algorithm::Assigned(coset::iana::Algorithm::ES256), &signing_key.pkey,
+            &nzcp.cose.signature, &sig_structure,
+        ).map_err(Error::Signature)?;
+        Ok(Verification::Verified)
+    }
+
+    pub fn merge(&mut self, other: PassSigningCerts) {
+        self.dscs.extend(other.dscs);
+        for (country, cscas) in other.cscas {
+            self.cscas.entry(country).or_default().extend(cscas);
+        }
+        for (key, hashes) in other.revocations {
+            self.revocations.entry(key).or_default().extend(hashes);
+        }
+        self.shc_keys.extend(other.shc_keys);
+        self.did_keys.extend(other.did_keys);
+    }
+
+    pub fn is_revoked(&self, kid: &[u8], hash_type: RevocationHashType, hash: &[u8]) -> bool {
+        self.revocations.iter()
+            .filter(|(k, _)| k.hash_type == hash_type && (kid.is_empty() || k.kid == kid))
+            .any(|(_, hashes)| hashes.contains(hash))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn revoke(&mut self, kid: &[u8], hash_type: RevocationHashType, hash: Vec<u8>) {
+        self.revocations.entry(RevocationKey {
+            kid: kid.to_vec(),
+            hash_type,
+        }).or_default().insert(hash);
+    }
+
+    fn remove_unchained_dscs(&mut self) -> Result<(), TrustListError> {
+        if self.cscas.is_empty() {
+            return Ok(());
+        }
+
+        let mut stores = std::collections::HashMap::new();
+        for (country, cscas) in &self.cscas {
+            let mut store = openssl::x509::store::X509StoreBuilder::new()
+                .map_err(|e| TrustListError::Crypto("Unable to create CSCA store".to_string(), e))?;
+            store.set_flags(openssl::x509::verify::X509VerifyFlags::NO_CHECK_TIME)
+                .map_err(|e| TrustListError::Crypto("Unable to create CSCA store".to_string(), e))?;
+            for csca in cscas {
+                store.add_cert(csca.clone())
+                    .map_err(|e| TrustListError::Crypto(format!("Unable to add CSCA for {}", country), e))?;
+            }
+            stores.insert(country.clone(), store.build());
+        }
+
+        let chain = openssl::stack::Stack::<openssl::x509::X509>::new()
+            .map_err(|e| TrustListError::Crypto("Unable to create certificate chain".to_string(), e))?;
+        self.dscs.retain(|key, dsc| {
+            let cert = match &dsc.cert {
+                Some(c) => c,
+                None => return true
+            };
+            let store = match stores.get(&key.iss) {
+                Some(s) => s,
+                None => {
+                    warn!("Dropping DSC {} for {}: no CSCA for country", base64::encode(&key.kid), key.iss);
+                    return false;
+                }
+            };
+            let verified = openssl::x509::X509StoreContext::new()
+                .and_then(|mut ctx| ctx.init(store, cert, &chain, |c| c.verify_cert()));
+            match verified {
+                Ok(true) => true,
+                Ok(false) => {
+                    warn!("Dropping DSC {} for {}: not issued by a CSCA of the country", base64::encode(&key.kid), key.iss);
+                    false
+                }
+                Err(e) => {
+                    warn!("Dropping DSC {} for {}: {}", base64::encode(&key.kid), key.iss, e);
+                    false
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+pub async fn load_trust_lists(sources: &[TrustListSource]) -> Result<PassSigningCerts, TrustListError> {
+    let mut signing_certs = PassSigningCerts::default();
+
+    for source in sources {
+        let certs = source.load().await?;
+        info!(
+            "Loaded {} signing keys, {} CSCAs and {} revoked hashes from {}",
+            certs.signing_key_count(), certs.cscas.values().map(|c| c.len()).sum::<usize>(),
+            certs.revocations.values().map(|h| h.len()).sum::<usize>(), source
+        );
+        signing_certs.merge(certs);
+    }
+
+    signing_certs.remove_unchained_dscs()?;
+
+    Ok(signing_certs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Verified,
+    Unverified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+pub enum ValidityPolicy {
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    #[serde(rename = "warn")]
+    Warn,
+    #[serde(rename = "ignore")]
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidityWarning {
+    Expired,
+    NotYetValid,
+}
+
+impl ValidityWarning {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ValidityWarning::Expired => "Expired",
+            ValidityWarning::NotYetValid => "Not yet valid",
+        }
+    }
+}
+
+pub fn check_validity(
+    nbf: Option<DateTime<Utc>>, exp: Option<DateTime<Utc>>, policy: ValidityPolicy,
+) -> Result<Option<ValidityWarning>, Error> {
+    let now = Utc::now();
+    let warning = if exp.is_some_and(|exp| exp < now) {
+        ValidityWarning::Expired
+    } else if nbf.is_some_and(|nbf| nbf > now) {
+        ValidityWarning::NotYetValid
+    } else {
+        return Ok(None);
+    };
+
+    match policy {
+        ValidityPolicy::Reject => Err(match warning {
+            ValidityWarning::Expired => Error::Expired,
+            ValidityWarning::NotYetValid => Error::NotYetValid,
+        }),
+        ValidityPolicy::Warn => Ok(Some(warning)),
+        ValidityPolicy::Ignore => Ok(None),
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct PassSigningCertKey {
+    pub kid: Vec<u8>,
+    pub iss: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DSCKeyUsage {
+    Test,
+    Vaccination,
+    Recovery,
+}
+
+#[derive(Debug)]
+pub struct PassSigningCert {
+    pub pkey: openssl::pkey::PKey<openssl::pkey::Public>,
+    pub cert: Option<openssl::x509::X509>,
+    pub key_usage: Option<Vec<DSCKeyUsage>>,
+}
+
+impl PassSigningCert {
+    pub fn check_valid_for(&self, iss: &str, iat: DateTime<Utc>) -> Result<(), Error> {
+        let cert = match &self.cert {
+            Some(c) => c,
+            None => return Ok(())
+        };
+
+        let country = cert.subject_name().entries_by_nid(openssl::nid::Nid::COUNTRYNAME).next()
+            .and_then(|c| c.data().as_utf8().ok())
+            .map(|c| c.to_string());
+        if country.as_deref() != Some(iss) {
+            return Err(Error::SigningCertificate("Signing certificate was not issued by the issuing country"));
+        }
+
+        let iat = openssl::asn1::Asn1Time::from_unix(iat.timestamp())
+            .map_err(|_| Error::SigningCertificate("Invalid issue time"))?;
+        if cert.not_before() > iat || cert.not_after() < iat {
+            return Err(Error::SigningCertificate("Signing certificate was not valid when issued"));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_key_usage(&self, group: &EUDigitalGreenCertGroup) -> Result<(), Error> {
+        let key_usage = match &self.key_usage {
+            Some(u) => u,
+            None => return Ok(())
+        };
+
+        match group {
+            EUDigitalGreenCertGroup::Vaccination(_) => if !key_usage.contains(&DSCKeyUsage::Vaccination) {
+                return Err(Error::SigningCertificate("Signing certificate is not allowed to sign vaccination certificates"));
+            },
+            EUDigitalGreenCertGroup::Test(_) => if !key_usage.contains(&DSCKeyUsage::Test) {
+                return Err(Error::SigningCertificate("Signing certificate is not allowed to sign test certificates"));
+            },
+            EUDigitalGreenCertGroup::Recovery(_) => if !key_usage.contains(&DSCKeyUsage::Recovery) {
+                return Err(Error::SigningCertificate("Signing certificate is not allowed to sign recovery certificates"));
+            },
+            EUDigitalGreenCertGroup::Exemption(_) => {
+                return Err(Error::SigningCertificate("Signing certificate is not allowed to sign exemption certificates"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn cose_key_id(cose_data: &coset::CoseSign1) -> &[u8] {
+    if !cose_data.protected.key_id.is_empty() {
+        &cose_data.protected.key_id
+    } else {
+        &cose_data.unprotected.key_id
+    }
+}
+
+fn revocation_hash(data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let hash = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), data)?;
+    Ok(hash[..16].to_vec())
+}
+
+pub fn revocation_hashes(
+    cose_data: &coset::CoseSign1, payload: &EHealthPayload,
+) -> Result<Vec<(RevocationHashType, Vec<u8>)>, openssl::error::ErrorStack> {
+    let mut hashes = vec![];
+
+    let signature = match cose_data.protected.alg {
+        Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES256)) |
+        Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES384)) |
+        Some(coset::Algorithm::Assigned(coset::iana::Algorithm::ES512)) => {
+            &cose_data.signature[..cose_data.signature.len() / 2]
+        }
+        _ => &cose_data.signature[..]
+    };
+    hashes.push((RevocationHashType::Signature, revocation_hash(signature)?));
+
+    let ucis: Vec<&str> = match &payload.hcert.eu_dgc_v1.group {
+        EUDigitalGreenCertGroup::Vaccination(v) => v.iter().map(|d| d.id.as_str()).collect(),
+        EUDigitalGreenCertGroup::Test(t) => t.iter().map(|d| d.id.as_str()).collect(),
+        EUDigitalGreenCertGroup::Recovery(r) => r.iter().map(|d| d.id.as_str()).collect(),
+        EUDigitalGreenCertGroup::Exemption(e) => e.iter().map(|d| d.id.as_str()).collect(),
+    };
+    for uci in ucis {
+        hashes.push((RevocationHashType::Uci, revocation_hash(uci.as_bytes())?));
+        hashes.push((
+            RevocationHashType::CountryCodeUci,
+            revocation_hash(format!("{}{}", payload.iss.to_uppercase(), uci).as_bytes())?
+        ));
+    }
+
+    Ok(hashes)
+}
+
+pub fn verify_cose_signature(cose_data: &coset::CoseSign1, signing_cert: &PassSigningCert) -> Result<(), SignatureError> {
+    let alg = cose_data.protected.alg.clone().unwrap_or_default();
+    cose_data.verify_signature(&[], |sig, data| {
+        verify_signature(&alg, &signing_cert.pkey, sig, data)
+    })
+}
+
+pub fn verify_signature(
+    alg: &coset::Algorithm, pkey: &openssl::pkey::PKeyRef<openssl::pkey::Public>, sig: &[u8], data: &[u8],
+) -> Result<(), SignatureError> {
+    let verified = match alg {
+        coset::Algorithm::Assigned(coset::iana::Algorithm::ES256) =>
+            verify_ecdsa_signature(openssl::hash::MessageDigest::sha256(), 32, pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::ES384) =>
+            verify_ecdsa_signature(openssl::hash::MessageDigest::sha384(), 48, pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::ES512) =>
+            verify_ecdsa_signature(openssl::hash::MessageDigest::sha512(), 66, pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::PS256) =>
+            verify_rsa_pss_signature(openssl::hash::MessageDigest::sha256(), pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::PS384) =>
+            verify_rsa_pss_signature(openssl::hash::MessageDigest::sha384(), pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::PS512) =>
+            verify_rsa_pss_signature(openssl::hash::MessageDigest::sha512(), pkey, sig, data)?,
+        coset::Algorithm::Assigned(coset::iana::Algorithm::EdDSA) => {
+            if pkey.id() != openssl::pkey::Id::ED25519 {
+                return Err(SignatureError::KeyType("EdDSA signature with a non Ed25519 key"));
+            }
+            let mut verifier = openssl::sign::Verifier::new_without_digest(pkey)?;
+            verifier.verify_oneshot(sig, data)?
+        }
+        a => {
+            return Err(SignatureError::UnsupportedAlgorithm(format!("{:?}", a)));
+        }
+    };
+
+    if verified {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+fn verify_ecdsa_signature(
+    digest: openssl::hash::MessageDigest, component_len: usize,
+    pkey: &openssl::pkey::PKeyRef<openssl::pkey::Public>, sig: &[u8], data: &[u8],
+) -> Result<bool, SignatureError> {
+    if sig.len() != component_len * 2 {
+        return Err(SignatureError::Length);
+    }
+    let r = openssl::bn::BigNum::from_slice(&sig[..component_len])?;
+    let s = openssl::bn::BigNum::from_slice(&sig[component_len..])?;
+    let sig = openssl::ecdsa::EcdsaSig::from_private_components(r, s)?;
+    let hash = openssl::hash::hash(digest, data)?;
+    Ok(sig.verify(hash.as_ref(), pkey.ec_key()?.as_ref())?)
+}
+
+fn verify_rsa_pss_signature(
+    digest: openssl::hash::MessageDigest,
+    pkey: &openssl::pkey::PKeyRef<openssl::pkey::Public>, sig: &[u8], data: &[u8],
+) -> Result<bool, SignatureError> {
+    let mut verifier = openssl::sign::Verifier::new(digest, pkey)?;
+    verifier.set_rsa_padding(openssl::rsa::Padding::PKCS1_PSS)?;
+    verifier.set_rsa_pss_saltlen(openssl::sign::RsaPssSaltlen::DIGEST_LENGTH)?;
+    verifier.set_rsa_mgf1_md(digest)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(sig)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, KeyIdLocation};
+
+    const ISSUED_AT: i64 = 1622316073;
+
+    fn trust_dsc(certs: &mut PassSigningCerts, iss: &str, kid: &[u8], key: &openssl::pkey::PKey<openssl::pkey::Private>) {
+        certs.dscs.insert(PassSigningCertKey {
+            iss: iss.to_string(),
+            kid: kid.to_vec(),
+        }, PassSigningCert {
+            pkey: test_support::public_key(key),
+            cert: None,
+            key_usage: None,
+        });
+    }
+
+    fn trust_dsc_cert(
+        certs: &mut PassSigningCerts, iss: &str, kid: &[u8], cert: openssl::x509::X509,
+    ) {
+        certs.dscs.insert(PassSigningCertKey {
+            iss: iss.to_string(),
+            kid: kid.to_vec(),
+        }, PassSigningCert {
+            pkey: cert.public_key().unwrap(),
+            key_usage: dsc_key_usage(&cert.to_der().unwrap()).unwrap(),
+            cert: Some(cert),
+        });
+    }
+
+    fn hc1(key: &openssl::pkey::PKey<openssl::pkey::Private>, kid: &[u8], kid_location: KeyIdLocation) -> HC1Certificate {
+        let data = test_support::sign_hc1(
+            test_support::cwt("DE", ISSUED_AT, test_support::vaccination(2)), key, kid, kid_location,
+        );
+        crate::decode::decode_hc1(&data, crate::decode::DecodeMode::Strict).unwrap()
+    }
+
+    #[test]
+    fn verifies_with_protected_kid() {
+        let key = test_support::signing_key();
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "DE", b"kid-1", &key);
+        trust_dsc(&mut certs, "DE", b"kid-2", &test_support::signing_key());
+
+        let cert = hc1(&key, b"kid-1", KeyIdLocation::Protected);
+        assert_eq!(cose_key_id(&cert.cose), b"kid-1");
+        assert_eq!(certs.candidates("DE", b"kid-1").len(), 1);
+        assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Verified);
+
+        let wrong_kid = hc1(&key, b"kid-2", KeyIdLocation::Protected);
+        assert!(matches!(certs.verify_hc1(&wrong_kid), Err(Error::Signature(_))));
+    }
+
+    #[test]
+    fn verifies_with_unprotected_kid() {
+        let key = test_support::signing_key();
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "DE", b"kid-1", &key);
+
+        let cert = hc1(&key, b"kid-1", KeyIdLocation::Unprotected);
+        assert!(cert.cose.protected.key_id.is_empty());
+        assert_eq!(cose_key_id(&cert.cose), b"kid-1");
+        assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Verified);
+
+        let unknown_kid = hc1(&key, b"kid-3", KeyIdLocation::Unprotected);
+        assert!(matches!(certs.verify_hc1(&unknown_kid), Err(Error::UnknownSigningKey)));
+    }
+
+    #[test]
+    fn tries_every_key_of_the_country_without_kid() {
+        let key = test_support::signing_key();
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "DE", b"kid-1", &test_support::signing_key());
+        trust_dsc(&mut certs, "DE", b"kid-2", &key);
+        trust_dsc(&mut certs, "AT", b"kid-3", &test_support::signing_key());
+
+        let cert = hc1(&key, b"", KeyIdLocation::None);
+        assert!(cose_key_id(&cert.cose).is_empty());
+        assert_eq!(certs.candidates("DE", b"").len(), 2);
+        assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Verified);
+
+        let unknown_key = hc1(&test_support::signing_key(), b"", KeyIdLocation::None);
+        assert!(matches!(certs.verify_hc1(&unknown_key), Err(Error::Signature(_))));
+    }
+
+    #[test]
+    fn signature_errors_carry_their_cause() {
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "DE", b"kid-1", &test_support::signing_key());
+
+        let cert = hc1(&test_support::signing_key(), b"kid-1", KeyIdLocation::Protected);
+        let err = certs.verify_hc1(&cert).unwrap_err();
+        assert!(matches!(
+            &err, Error::Signature(SignatureError::NoKeyMatched(f)) if matches!(f.as_slice(), [SignatureError::Mismatch])
+        ));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    fn round_trip(alg: coset::iana::Algorithm, key: &openssl::pkey::PKey<openssl::pkey::Private>) {
+        let alg_name = format!("{:?}", alg);
+        let data = b"Signature1 structure";
+        let sig = test_support::sign(alg, key, data);
+        let alg = coset::Algorithm::Assigned(alg);
+        let pkey = test_support::public_key(key);
+        assert!(verify_signature(&alg, &pkey, &sig, data).is_ok(), "{} signature did not verify", alg_name);
+        assert!(
+            matches!(verify_signature(&alg, &pkey, &sig, b"Signature1 structurf"), Err(SignatureError::Mismatch)),
+            "{} signature verified over a tampered payload", alg_name
+        );
+    }
+
+    #[test]
+    fn verifies_ecdsa_signatures() {
+        round_trip(coset::iana::Algorithm::ES256, &test_support::signing_key());
+        round_trip(coset::iana::Algorithm::ES384, &test_support::ec_signing_key(openssl::nid::Nid::SECP384R1));
+        round_trip(coset::iana::Algorithm::ES512, &test_support::ec_signing_key(openssl::nid::Nid::SECP521R1));
+
+        let key = test_support::ec_signing_key(openssl::nid::Nid::SECP521R1);
+        let sig = test_support::sign(coset::iana::Algorithm::ES512, &key, b"data");
+        assert_eq!(sig.len(), 132);
+        assert!(matches!(verify_signature(
+            &coset::Algorithm::Assigned(coset::iana::Algorithm::ES512), &test_support::public_key(&key),
+            &sig[..128], b"data",
+        ), Err(SignatureError::Length)));
+    }
+
+    #[test]
+    fn verifies_rsa_pss_signatures() {
+        let key = openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        round_trip(coset::iana::Algorithm::PS256, &key);
+        round_trip(coset::iana::Algorithm::PS384, &key);
+        round_trip(coset::iana::Algorithm::PS512, &key);
+    }
+
+    #[test]
+    fn verifies_eddsa_signatures() {
+        let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        round_trip(coset::iana::Algorithm::EdDSA, &key);
+
+        let sig = test_support::sign(coset::iana::Algorithm::EdDSA, &key, b"data");
+        assert!(matches!(verify_signature(
+            &coset::Algorithm::Assigned(coset::iana::Algorithm::EdDSA),
+            &test_support::public_key(&test_support::signing_key()), &sig, b"data",
+        ), Err(SignatureError::KeyType(_))));
+    }
+
+    #[test]
+    fn drops_dscs_not_issued_by_a_csca_of_their_country() {
+        let de_csca_key = test_support::signing_key();
+        let de_csca = test_support::csca("DE", &de_csca_key);
+        let fr_csca_key = test_support::signing_key();
+        let fr_csca = test_support::csca("FR", &fr_csca_key);
+        let valid = (ISSUED_AT - 86400, ISSUED_AT + 86400);
+
+        let mut certs = PassSigningCerts::default();
+        certs.cscas.insert("DE".to_string(), vec![de_csca.clone()]);
+        certs.cscas.insert("FR".to_string(), vec![fr_csca.clone()]);
+        trust_dsc_cert(&mut certs, "DE", b"chained", test_support::dsc(
+            &de_csca, &de_csca_key, "DE", &test_support::signing_key(), valid.0, valid.1, &[],
+        ));
+        trust_dsc_cert(&mut certs, "DE", b"french", test_support::dsc(
+            &fr_csca, &fr_csca_key, "DE", &test_support::signing_key(), valid.0, valid.1, &[],
+        ));
+        trust_dsc_cert(&mut certs, "IT", b"no-csca", test_support::dsc(
+            &de_csca, &de_csca_key, "IT", &test_support::signing_key(), valid.0, valid.1, &[],
+        ));
+        trust_dsc(&mut certs, "DE", b"raw-key", &test_support::signing_key());
+
+        certs.remove_unchained_dscs().unwrap();
+        assert_eq!(certs.candidates("DE", b"chained").len(), 1);
+        assert!(certs.candidates("DE", b"french").is_empty());
+        assert!(!certs.has_country("IT"));
+        assert_eq!(certs.candidates("DE", b"raw-key").len(), 1);
+    }
+
+    #[test]
+    fn rejects_dscs_outside_their_validity_or_country() {
+        let csca_key = test_support::signing_key();
+        let csca = test_support::csca("DE", &csca_key);
+        let key = test_support::signing_key();
+        let dsc = |not_before, not_after| {
+            let cert = test_support::dsc(&csca, &csca_key, "DE", &key, not_before, not_after, &[]);
+            PassSigningCert {
+                pkey: cert.public_key().unwrap(),
+                cert: Some(cert),
+                key_usage: None,
+            }
+        };
+        let iat = Utc.timestamp_opt(ISSUED_AT, 0).unwrap();
+
+        let valid = dsc(ISSUED_AT - 86400, ISSUED_AT + 86400);
+        assert!(valid.check_valid_for("DE", iat).is_ok());
+        assert!(matches!(valid.check_valid_for("FR", iat), Err(Error::SigningCertificate(_))));
+
+        let expired = dsc(ISSUED_AT - 2 * 86400, ISSUED_AT - 86400);
+        assert!(matches!(expired.check_valid_for("DE", iat), Err(Error::SigningCertificate(_))));
+        let not_yet_valid = dsc(ISSUED_AT + 86400, ISSUED_AT + 2 * 86400);
+        assert!(matches!(not_yet_valid.check_valid_for("DE", iat), Err(Error::SigningCertificate(_))));
+
+        let mut certs = PassSigningCerts::default();
+        trust_dsc_cert(&mut certs, "DE", b"kid-1", expired.cert.unwrap());
+        let cert = hc1(&key, b"kid-1", KeyIdLocation::Protected);
+        assert!(matches!(certs.verify_hc1(&cert), Err(Error::SigningCertificate(_))));
+    }
+
+    #[test]
+    fn enforces_dsc_extended_key_usage() {
+        let csca_key = test_support::signing_key();
+        let csca = test_support::csca("DE", &csca_key);
+        let key = test_support::signing_key();
+        let dsc = |extended_key_usage: &[&str]| test_support::dsc(
+            &csca, &csca_key, "DE", &key, ISSUED_AT - 86400, ISSUED_AT + 86400, extended_key_usage,
+        );
+
+        let test_only = dsc(&[DSC_EKU_TEST[0], "1.3.6.1.5.5.7.3.2"]);
+        assert_eq!(dsc_key_usage(&test_only.to_der().unwrap()).unwrap(), Some(vec![DSCKeyUsage::Test]));
+        assert_eq!(
+            dsc_key_usage(&dsc(&[DSC_EKU_VACCINATION[1]]).to_der().unwrap()).unwrap(), Some(vec![DSCKeyUsage::Vaccination]),
+        );
+        assert_eq!(dsc_key_usage(&dsc(&["1.3.6.1.5.5.7.3.2"]).to_der().unwrap()).unwrap(), None);
+        assert_eq!(dsc_key_usage(&dsc(&[]).to_der().unwrap()).unwrap(), None);
+
+        let mut certs = PassSigningCerts::default();
+        trust_dsc_cert(&mut certs, "DE", b"test-only", test_only);
+        trust_dsc_cert(&mut certs, "DE", b"no-eku", dsc(&[]));
+
+        let vaccination = hc1(&key, b"test-only", KeyIdLocation::Protected);
+        assert!(matches!(certs.verify_hc1(&vaccination), Err(Error::SigningCertificate(_))));
+        let test = crate::decode::decode_hc1(&test_support::sign_hc1(
+            test_support::cwt("DE", ISSUED_AT, test_support::test("2021-05-29T10:00:00Z")), &key, b"test-only",
+            KeyIdLocation::Protected,
+        ), crate::decode::DecodeMode::Strict).unwrap();
+        assert_eq!(certs.verify_hc1(&test).unwrap(), Verification::Verified);
+
+        let vaccination = hc1(&key, b"no-eku", KeyIdLocation::Protected);
+        assert_eq!(certs.verify_hc1(&vaccination).unwrap(), Verification::Verified);
+    }
+
+    #[test]
+    fn refuses_revoked_certificates() {
+        let key = test_support::signing_key();
+        let cert = hc1(&key, b"kid-1", KeyIdLocation::Protected);
+        let sha256_prefix = |data: &[u8]| {
+            openssl::hash::hash(openssl::hash::MessageDigest::sha256(), data).unwrap()[..16].to_vec()
+        };
+        let uci = "URN:UVCI:01DE/IZ12345A/5CWLU12RNOB9RXSEOP6FG8#2";
+
+        let listed = [
+            (RevocationHashType::Uci, sha256_prefix(uci.as_bytes())),
+            (RevocationHashType::CountryCodeUci, sha256_prefix(format!("DE{}", uci).as_bytes())),
+            (RevocationHashType::Signature, sha256_prefix(&cert.cose.signature[..32])),
+        ];
+        for (hash_type, hash) in listed {
+            let mut certs = PassSigningCerts::default();
+            trust_dsc(&mut certs, "DE", b"kid-1", &key);
+            certs.revoke(b"kid-1", hash_type, hash.clone());
+            assert!(matches!(certs.verify_hc1(&cert), Err(Error::Revoked)), "{:?} hash was not refused", hash_type);
+
+            let mut certs = PassSigningCerts::default();
+            trust_dsc(&mut certs, "DE", b"kid-1", &key);
+            certs.revoke(b"kid-2", hash_type, hash);
+            assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Verified);
+        }
+
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "DE", b"kid-1", &key);
+        certs.revoke(b"kid-1", RevocationHashType::Uci, sha256_prefix(b"URN:UVCI:01DE/IZ12345A/OTHER"));
+        assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Verified);
+    }
+
+    const SHC_ISSUER: &str = "https://shc.example.com/issuer";
+
+    fn jwk(kid: &str, key: &openssl::pkey::PKey<openssl::pkey::Private>) -> serde_json::Value {
+        let ec_key = key.ec_key().unwrap();
+        let mut x = openssl::bn::BigNum::new().unwrap();
+        let mut y = openssl::bn::BigNum::new().unwrap();
+        ec_key.public_key().affine_coordinates(
+            ec_key.group(), &mut x, &mut y, &mut openssl::bn::BigNumContext::new().unwrap(),
+        ).unwrap();
+        serde_json::json!({
+            "kty": "EC",
+            "kid": kid,
+            "use": "sig",
+            "alg": "ES256",
+            "crv": "P-256",
+            "x": base64::encode_config(x.to_vec_padded(32).unwrap(), base64::URL_SAFE_NO_PAD),
+            "y": base64::encode_config(y.to_vec_padded(32).unwrap(), base64::URL_SAFE_NO_PAD),
+        })
+    }
+
+    fn shc(iss: &str, kid: &str, key: &openssl::pkey::PKey<openssl::pkey::Private>) -> SmartHealthCard {
+        let header = base64::encode_config(
+            serde_json::json!({"alg": "ES256", "kid": kid}).to_string(), base64::URL_SAFE_NO_PAD,
+        );
+        let payload = base64::encode_config(serde_json::json!({
+            "iss": iss,
+            "nbf": ISSUED_AT,
+            "vc": {
+                "type": ["https://smarthealth.cards#health-card"],
+                "credentialSubject": {
+                    "fhirVersion": "4.0.1",
+                    "fhirBundle": {"resourceType": "Bundle", "entry": []},
+                },
+            },
+        }).to_string(), base64::URL_SAFE_NO_PAD);
+        let signing_input = format!("{}.{}", header, payload);
+        let signature = test_support::sign(coset::iana::Algorithm::ES256, key, signing_input.as_bytes());
+        crate::shc::decode_jws(&format!(
+            "{}.{}", signing_input, base64::encode_config(signature, base64::URL_SAFE_NO_PAD),
+        )).unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn loads_shc_issuer_keys_from_directory() {
+        let key = test_support::signing_key();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("vci-issuers.json"), serde_json::json!({
+            "participating_issuers": [
+                {"iss": format!("{}/", SHC_ISSUER), "name": "Example"},
+                {"iss": "https://missing.example.com", "name": "Missing"},
+                {"iss": "not-a-url", "name": "Invalid"},
+            ],
+        }).to_string()).unwrap();
+        let well_known = dir.path().join("shc.example.com/issuer/.well-known");
+        std::fs::create_dir_all(&well_known).unwrap();
+        let mut p384_key = jwk("p-384", &test_support::signing_key());
+        p384_key["crv"] = serde_json::json!("P-384");
+        std::fs::write(well_known.join("jwks.json"), serde_json::json!({
+            "keys": [jwk("key-1", &key), p384_key, {"kty": "EC", "crv": "P-256"}],
+        }).to_string()).unwrap();
+
+        let certs = TrustListSource::SHCIssuerDir(dir.path().to_str().unwrap().to_string()).load().await.unwrap();
+        assert_eq!(certs.signing_key_count(), 1);
+        assert!(certs.has_shc_issuer(SHC_ISSUER));
+        assert_eq!(certs.shc_candidates(SHC_ISSUER, Some("key-1")).len(), 1);
+        assert_eq!(certs.verify_shc(&shc(SHC_ISSUER, "key-1", &key)).unwrap(), Verification::Verified);
+
+        let err = TrustListSource::SHCIssuerDir(dir.path().join("missing").to_str().unwrap().to_string())
+            .load().await.unwrap_err();
+        assert!(matches!(err, TrustListError::Io(_, _)));
+    }
+
+    #[test]
+    fn verifies_shc_signatures() {
+        let key = test_support::signing_key();
+        let mut certs = PassSigningCerts::default();
+        certs.shc_keys.insert(PassSigningCertKey {
+            iss: SHC_ISSUER.to_string(),
+            kid: b"key-1".to_vec(),
+        }, PassSigningCert {
+            pkey: test_support::public_key(&key),
+            cert: None,
+            key_usage: None,
+        });
+
+        assert_eq!(certs.verify_shc(&shc(SHC_ISSUER, "key-1", &key)).unwrap(), Verification::Verified);
+        assert_eq!(
+            certs.verify_shc(&shc(&format!("{}/", SHC_ISSUER), "key-1", &key)).unwrap(), Verification::Verified,
+        );
+        assert_eq!(
+            certs.verify_shc(&shc("https://other.example.com", "key-1", &key)).unwrap(), Verification::Unverified,
+        );
+        assert!(matches!(certs.verify_shc(&shc(SHC_ISSUER, "key-2", &key)), Err(Error::UnknownSigningKey)));
+
+        let forged = shc(SHC_ISSUER, "key-1", &test_support::signing_key());
+        assert!(matches!(
+            certs.verify_shc(&forged), Err(Error::Signature(SignatureError::NoKeyMatched(f))) if f.len() == 1
+        ));
+
+        let mut tampered = shc(SHC_ISSUER, "key-1", &key);
+        tampered.signing_input.push('A');
+        assert!(matches!(certs.verify_shc(&tampered), Err(Error::Signature(_))));
+
+        let mut wrong_alg = shc(SHC_ISSUER, "key-1", &key);
+        wrong_alg.header.alg = "RS256".to_string();
+        assert!(matches!(
+            certs.verify_shc(&wrong_alg), Err(Error::Signature(SignatureError::UnsupportedAlgorithm(_)))
+        ));
+    }
+
+    #[test]
+    fn applies_validity_policy() {
+        let past = Some(Utc::now() - chrono::Duration::days(1));
+        let future = Some(Utc::now() + chrono::Duration::days(1));
+
+        assert!(matches!(check_validity(future, None, ValidityPolicy::Reject), Err(Error::NotYetValid)));
+        assert!(matches!(check_validity(None, past, ValidityPolicy::Reject), Err(Error::Expired)));
+        assert_eq!(check_validity(future, None, ValidityPolicy::Warn).unwrap(), Some(ValidityWarning::NotYetValid));
+        assert_eq!(check_validity(None, past, ValidityPolicy::Warn).unwrap(), Some(ValidityWarning::Expired));
+        assert_eq!(check_validity(future, None, ValidityPolicy::Ignore).unwrap(), None);
+        assert_eq!(check_validity(None, past, ValidityPolicy::Ignore).unwrap(), None);
+
+        assert_eq!(check_validity(past, future, ValidityPolicy::Reject).unwrap(), None);
+        assert_eq!(check_validity(None, None, ValidityPolicy::Reject).unwrap(), None);
+        assert_eq!(check_validity(future, past, ValidityPolicy::Warn).unwrap(), Some(ValidityWarning::Expired));
+    }
+
+    #[test]
+    fn leaves_unknown_countries_unverified() {
+        let key = test_support::signing_key();
+        let mut certs = PassSigningCerts::default();
+        trust_dsc(&mut certs, "AT", b"kid-1", &key);
+
+        let cert = hc1(&key, b"kid-1", KeyIdLocation::Protected);
+        assert_eq!(certs.verify_hc1(&cert).unwrap(), Verification::Unverified);
+    }
+}